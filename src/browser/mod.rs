@@ -4,6 +4,8 @@ use std::future::Future;
 use web_sys::*;
 use futures::channel::mpsc::*;
 use wasm_bindgen::{JsCast, JsValue, closure, prelude::Closure};
+use crate::error::GameError;
+pub mod storage;
 
 pub fn window() -> Window {
     web_sys::window().expect("can not get window")
@@ -17,6 +19,10 @@ pub fn element<T: JsCast>(id: &str) -> Option<T> {
     document().get_element_by_id(id)?.dyn_into::<T>().ok()
 }
 
+pub fn query_string() -> String {
+    window().location().search().unwrap_or_default()
+}
+
 pub fn now() -> f64 {
     window().performance().unwrap().now()
 }
@@ -26,7 +32,19 @@ pub enum KeyPress {
     KeyUp(KeyboardEvent)
 }
 
-pub fn prepare_input() -> Result<UnboundedReceiver<KeyPress>, JsValue> {
+// positions are in canvas pixels
+pub enum MouseInput {
+    Down { x: f64, y: f64, button: i16 },
+    Move { x: f64, y: f64 },
+}
+
+pub enum WindowEvent {
+    Blur,
+    Hidden,
+    Visible,
+}
+
+pub fn prepare_input() -> Result<UnboundedReceiver<KeyPress>, GameError> {
     let (keydown_sender, receiver) = unbounded();
     let keydown_sender = Rc::new(RefCell::new(keydown_sender));
     let keyup_sender = Rc::clone(&keydown_sender);
@@ -36,7 +54,7 @@ pub fn prepare_input() -> Result<UnboundedReceiver<KeyPress>, JsValue> {
     let on_keyup: Closure<dyn FnMut(KeyboardEvent)> = wasm_bindgen::closure::Closure::new(move |event: KeyboardEvent| {
         keyup_sender.borrow_mut().start_send(KeyPress::KeyUp(event));
     });
-    let canvas = element::<HtmlCanvasElement>("game_canvas").unwrap();
+    let canvas = element::<HtmlCanvasElement>("game_canvas").ok_or(GameError::ElementNotFound("game_canvas".to_string()))?;
     canvas.set_onkeydown(Some(on_keydown.as_ref().unchecked_ref()));
     canvas.set_onkeyup(Some(on_keyup.as_ref().unchecked_ref()));
     on_keydown.forget();
@@ -44,6 +62,97 @@ pub fn prepare_input() -> Result<UnboundedReceiver<KeyPress>, JsValue> {
     Ok(receiver)
 }
 
+pub fn prepare_mouse_input() -> Result<UnboundedReceiver<MouseInput>, GameError> {
+    let (down_sender, receiver) = unbounded();
+    let down_sender = Rc::new(RefCell::new(down_sender));
+    let move_sender = Rc::clone(&down_sender);
+    let canvas = element::<HtmlCanvasElement>("game_canvas").ok_or(GameError::ElementNotFound("game_canvas".to_string()))?;
+    // the canvas may be scaled by css, events come in css pixels
+    let to_canvas = {
+        let canvas = canvas.clone();
+        move |event: &MouseEvent| {
+            let scale_x = canvas.width() as f64 / canvas.client_width().max(1) as f64;
+            let scale_y = canvas.height() as f64 / canvas.client_height().max(1) as f64;
+            (event.offset_x() as f64 * scale_x, event.offset_y() as f64 * scale_y)
+        }
+    };
+    let to_canvas_down = to_canvas.clone();
+    let on_mousedown: Closure<dyn FnMut(MouseEvent)> = wasm_bindgen::closure::Closure::new(move |event: MouseEvent| {
+        let (x, y) = to_canvas_down(&event);
+        down_sender.borrow_mut().start_send(MouseInput::Down { x, y, button: event.button() });
+    });
+    let on_mousemove: Closure<dyn FnMut(MouseEvent)> = wasm_bindgen::closure::Closure::new(move |event: MouseEvent| {
+        let (x, y) = to_canvas(&event);
+        move_sender.borrow_mut().start_send(MouseInput::Move { x, y });
+    });
+    // right clicks are used by the game, not for the browser menu
+    let on_contextmenu: Closure<dyn FnMut(MouseEvent)> = wasm_bindgen::closure::Closure::new(move |event: MouseEvent| {
+        event.prevent_default();
+    });
+    canvas.set_onmousedown(Some(on_mousedown.as_ref().unchecked_ref()));
+    canvas.set_onmousemove(Some(on_mousemove.as_ref().unchecked_ref()));
+    canvas.set_oncontextmenu(Some(on_contextmenu.as_ref().unchecked_ref()));
+    on_mousedown.forget();
+    on_mousemove.forget();
+    on_contextmenu.forget();
+    Ok(receiver)
+}
+
+// offers `contents` as a file download through a temporary link
+pub fn download(filename: &str, mime: &str, contents: &str) -> Result<(), GameError> {
+    let link = document().create_element("a")?
+        .dyn_into::<HtmlElement>()
+        .map_err(|_| GameError::Js("can not create link".to_string()))?;
+    link.set_attribute("href", &format!("data:{};charset=utf-8,{}", mime, percent_encode(contents)))?;
+    link.set_attribute("download", filename)?;
+    link.click();
+    Ok(())
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// lets the user pick a file and resolves to its text, a cancelled dialog never resolves
+pub async fn pick_file(accept: &str) -> Result<String, GameError> {
+    let input = document().create_element("input")?
+        .dyn_into::<HtmlInputElement>()
+        .map_err(|_| GameError::Js("can not create file input".to_string()))?;
+    input.set_type("file");
+    input.set_accept(accept);
+    let reader = FileReader::new()?;
+    let (sender, receiver) = futures::channel::oneshot::channel::<Result<String, GameError>>();
+    let sender = Rc::new(Mutex::new(Some(sender)));
+    let load_reader = reader.clone();
+    let on_load = closure::Closure::once(move || {
+        if let Some(sender) = sender.lock().ok().and_then(| mut sender | sender.take()) {
+            let text = load_reader.result().ok()
+                .and_then(|text| text.as_string())
+                .ok_or_else(|| GameError::Js("can not read file".to_string()));
+            sender.send(text);
+        };
+    });
+    reader.set_onload(Some(on_load.as_ref().unchecked_ref()));
+    let change_input = input.clone();
+    let on_change = closure::Closure::once(move || {
+        if let Some(file) = change_input.files().and_then(|files| files.get(0)) {
+            if let Err(err) = reader.read_as_text(&file) {
+                crate::log_error!("can not read {}: {:?}", file.name(), err);
+            }
+        }
+    });
+    input.set_onchange(Some(on_change.as_ref().unchecked_ref()));
+    input.click();
+    receiver.await.map_err(|_| GameError::Js("file dialog closed".to_string()))?
+}
+
 pub fn spawn_local<F>(future: F) 
 where
     F: Future<Output = ()> + 'static,
@@ -51,7 +160,7 @@ where
     wasm_bindgen_futures::spawn_local(future);
 }
 
-pub async fn load_image(src: &str) -> Result<HtmlImageElement, JsValue> {
+pub async fn load_image(src: &str) -> Result<HtmlImageElement, GameError> {
     let new_image = web_sys::HtmlImageElement::new()?;
     let (success_tx, success_rs) = futures::channel::oneshot::channel::<Result<(), JsValue>>();
     let success_tx = Rc::new(Mutex::new(Some(success_tx)));
@@ -69,7 +178,9 @@ pub async fn load_image(src: &str) -> Result<HtmlImageElement, JsValue> {
     new_image.set_onload(Some(success_cb.as_ref().unchecked_ref()));
     new_image.set_onerror(Some(error_cb.as_ref().unchecked_ref()));
     new_image.set_src(src);
-    success_rs.await.map_err(|_| JsValue::from_str("can not get image"))??;
+    success_rs.await
+        .map_err(|_| GameError::AssetLoad { src: src.to_string() })?
+        .map_err(|_| GameError::AssetLoad { src: src.to_string() })?;
     Ok(new_image)
 }
 
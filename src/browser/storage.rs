@@ -0,0 +1,110 @@
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use web_sys::Storage;
+use crate::error::GameError;
+use super::window;
+
+// every value is stored inside an envelope carrying the schema version it was written with
+#[derive(Serialize, Deserialize, Debug)]
+struct Envelope {
+    version: u32,
+    data: Value,
+}
+
+pub trait Persisted: Serialize + DeserializeOwned + Default {
+    const KEY: &'static str;
+    const VERSION: u32;
+
+    // upgrades `data` written with schema `version` to `version + 1`
+    fn migrate(version: u32, _data: Value) -> Result<Value, String> {
+        Err(format!("no migration from version {}", version))
+    }
+}
+
+pub fn encode<T: Persisted>(value: &T) -> Result<String, String> {
+    let data = serde_json::to_value(value).map_err(|err| err.to_string())?;
+    serde_json::to_string(&Envelope { version: T::VERSION, data }).map_err(|err| err.to_string())
+}
+
+pub fn decode<T: Persisted>(raw: &str) -> Result<T, String> {
+    let envelope: Envelope = serde_json::from_str(raw).map_err(|err| err.to_string())?;
+    if envelope.version > T::VERSION {
+        return Err(format!("stored version {} is newer than supported version {}", envelope.version, T::VERSION));
+    }
+    let mut data = envelope.data;
+    for version in envelope.version..T::VERSION {
+        data = T::migrate(version, data)?;
+    }
+    serde_json::from_value(data).map_err(|err| err.to_string())
+}
+
+fn local_storage() -> Result<Storage, GameError> {
+    window().local_storage()?.ok_or(GameError::Storage("local storage is not available".to_string()))
+}
+
+// missing or unreadable values fall back to the default so a bad entry never blocks the game
+pub fn load<T: Persisted>() -> T {
+    let raw = match local_storage().and_then(|storage| Ok(storage.get_item(T::KEY)?)) {
+        Ok(Some(raw)) => raw,
+        Ok(None) => return T::default(),
+        Err(err) => {
+            crate::log_warn!("can not read `{}`: {}", T::KEY, err);
+            return T::default();
+        },
+    };
+    decode(&raw).unwrap_or_else(|err| {
+        crate::log_warn!("discarding stored `{}`: {}", T::KEY, err);
+        T::default()
+    })
+}
+
+pub fn save<T: Persisted>(value: &T) -> Result<(), GameError> {
+    let raw = encode(value).map_err(GameError::Storage)?;
+    local_storage()?.set_item(T::KEY, &raw)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct Volume {
+        level: f64,
+        muted: bool,
+    }
+
+    // version 1 stored the level as a percentage and had no `muted` flag
+    impl Persisted for Volume {
+        const KEY: &'static str = "test.volume";
+        const VERSION: u32 = 2;
+
+        fn migrate(version: u32, data: Value) -> Result<Value, String> {
+            match version {
+                1 => {
+                    let percent = data.get("percent").and_then(Value::as_f64).ok_or("missing percent")?;
+                    Ok(serde_json::json!({ "level": percent / 100.0, "muted": false }))
+                },
+                _ => Err(format!("no migration from version {}", version)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let volume = Volume { level: 0.5, muted: true };
+        assert_eq!(volume, decode::<Volume>(&encode(&volume).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_migration() {
+        let volume = decode::<Volume>(r#"{ "version": 1, "data": { "percent": 80.0 } }"#).unwrap();
+        assert_eq!(Volume { level: 0.8, muted: false }, volume);
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        assert!(decode::<Volume>(r#"{ "version": 3, "data": {} }"#).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+use serde::{Serialize, Deserialize};
+use crate::error::GameError;
+use crate::engine::network::get;
+
+pub const CONFIG_URL: &str = "config.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub server_url: String,
+    // makes random tracks and the training server's populations repeatable, training spawns and evaluates without chance
+    pub seed: Option<u64>,
+    pub launch: LaunchConfig,
+    pub spawn: SpawnConfig,
+    pub cars: Vec<CarConfig>,
+    pub race: RaceConfig,
+    pub training: TrainingConfig,
+    pub generator: GeneratorConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchMode {
+    Menu,
+    Race,
+    Training,
+}
+
+// lets a setup skip the menus and start straight into a mode and track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LaunchConfig {
+    pub mode: LaunchMode,
+    pub track: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnConfig {
+    pub x: f64,
+    pub y: f64,
+    pub rotate: f64,
+}
+
+// one entry per car class, `name` is what the race and training sections refer to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CarConfig {
+    pub name: String,
+    pub sprite: String,
+    pub width: f64,
+    pub height: f64,
+    pub mass: f64,
+    pub engine_force: f64,
+    pub top_speed: f64,
+    pub max_steer: f64,
+    pub grip: f64,
+    pub sensor_range: f64,
+    // fuel is in mass units and burns per pixel at full throttle, tires wear per pixel driven
+    pub fuel_capacity: f64,
+    pub fuel_consumption: f64,
+    pub tire_wear_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaceConfig {
+    pub player_car: String,
+    // opponents are driven by the current population of their class
+    pub opponents: Vec<String>,
+    // seconds a car stands still in the pit lane to refuel and change tires
+    pub pit_stop_time: f64,
+    // arcade pickups on the track, never used in training
+    pub pickups: bool,
+    // the race is over once the player has driven this many laps, with 0 it lasts until every car is out
+    pub laps: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrainingConfig {
+    // every class is evolved as its own population of `num_cars`
+    pub car_classes: Vec<String>,
+    pub num_cars: u32,
+    pub evaluate_interval: f64,
+    pub stale_timeout: f64,
+    pub discrete_outputs: bool,
+    // end a run on the first boundary crossing instead of letting the car continue off track
+    pub off_track_fatal: bool,
+    // feed the car damage to the network as an extra input
+    pub damage_input: bool,
+    // feed the fuel level and tire wear to the network as extra inputs
+    pub strategy_inputs: bool,
+}
+
+// random tracks added to the track list, generated from `seed` so a run can be repeated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratorConfig {
+    pub tracks: u32,
+    // the area a track may cover, in tiles
+    pub width: u32,
+    pub height: u32,
+    // from 0 for a plain rectangle to 1 for as many turns as fit
+    pub difficulty: f64,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            server_url: "http://127.0.0.1:3030".to_string(),
+            seed: None,
+            launch: LaunchConfig::default(),
+            spawn: SpawnConfig::default(),
+            cars: vec![
+                CarConfig {
+                    name: "no5".to_string(),
+                    sprite: "car_5.png".to_string(),
+                    mass: 1.2,
+                    engine_force: 540.0,
+                    top_speed: 450.0,
+                    grip: 1.1,
+                    ..CarConfig::default()
+                },
+                CarConfig::default(),
+            ],
+            race: RaceConfig::default(),
+            training: TrainingConfig::default(),
+            generator: GeneratorConfig::default(),
+        }
+    }
+}
+
+impl Default for LaunchConfig {
+    fn default() -> Self {
+        LaunchConfig { mode: LaunchMode::Menu, track: None }
+    }
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        SpawnConfig { x: 400.0, y: 80.0, rotate: 0.0 }
+    }
+}
+
+impl Default for CarConfig {
+    fn default() -> Self {
+        CarConfig {
+            name: "no8".to_string(),
+            sprite: "car_8.png".to_string(),
+            width: 63.0,
+            height: 38.0,
+            mass: 1.0,
+            engine_force: 400.0,
+            top_speed: 500.0,
+            max_steer: 0.6,
+            grip: 1.0,
+            sensor_range: 200.0,
+            // roughly five laps of fuel and eight of tires on the classic track
+            fuel_capacity: 0.3,
+            fuel_consumption: 0.0000125,
+            tire_wear_rate: 0.000025,
+        }
+    }
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        RaceConfig { player_car: "no8".to_string(), opponents: vec![], pit_stop_time: 3.0, pickups: true, laps: 3 }
+    }
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig { car_classes: vec!["no8".to_string()], num_cars: 50, evaluate_interval: 0.3, stale_timeout: 2.0, discrete_outputs: false, off_track_fatal: false, damage_input: false, strategy_inputs: false }
+    }
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig { tracks: 0, width: 10, height: 5, difficulty: 0.5 }
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query.trim_start_matches('?')
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    },
+                    None => decoded.push(b'%'),
+                }
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn positive(name: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("`{}` must be a positive number, got {}", name, value));
+    }
+    Ok(())
+}
+
+fn non_negative(name: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("`{}` must be zero or more, got {}", name, value));
+    }
+    Ok(())
+}
+
+impl GameConfig {
+    pub fn from_json(json: &str) -> Result<Self, GameError> {
+        let config: GameConfig = serde_json::from_str(json).map_err(|err| GameError::Config(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), GameError> {
+        let check = || -> Result<(), String> {
+            if !(self.server_url.starts_with("http://") || self.server_url.starts_with("https://")) {
+                return Err(format!("`server_url` must start with http:// or https://, got `{}`", self.server_url));
+            }
+            if !self.spawn.x.is_finite() || !self.spawn.y.is_finite() || !self.spawn.rotate.is_finite() {
+                return Err("`spawn` must only contain finite numbers".to_string());
+            }
+            for (index, car) in self.cars.iter().enumerate() {
+                if car.name.is_empty() {
+                    return Err(format!("`cars[{}].name` must not be empty", index));
+                }
+                if self.cars[..index].iter().any(|other| other.name == car.name) {
+                    return Err(format!("car `{}` is defined more than once", car.name));
+                }
+                let field = |name: &str| format!("cars[{}].{}", car.name, name);
+                positive(&field("width"), car.width)?;
+                positive(&field("height"), car.height)?;
+                positive(&field("mass"), car.mass)?;
+                positive(&field("engine_force"), car.engine_force)?;
+                positive(&field("top_speed"), car.top_speed)?;
+                positive(&field("max_steer"), car.max_steer)?;
+                positive(&field("grip"), car.grip)?;
+                positive(&field("sensor_range"), car.sensor_range)?;
+                positive(&field("fuel_capacity"), car.fuel_capacity)?;
+                non_negative(&field("fuel_consumption"), car.fuel_consumption)?;
+                non_negative(&field("tire_wear_rate"), car.tire_wear_rate)?;
+            }
+            let classes = std::iter::once(&self.race.player_car).chain(&self.race.opponents).chain(&self.training.car_classes);
+            for name in classes {
+                if self.car(name).is_none() {
+                    return Err(format!("unknown car `{}`", name));
+                }
+            }
+            non_negative("race.pit_stop_time", self.race.pit_stop_time)?;
+            if self.training.car_classes.is_empty() {
+                return Err("`training.car_classes` must name at least one car".to_string());
+            }
+            positive("training.evaluate_interval", self.training.evaluate_interval)?;
+            positive("training.stale_timeout", self.training.stale_timeout)?;
+            if self.training.num_cars == 0 {
+                return Err("`training.num_cars` must be at least 1".to_string());
+            }
+            if self.generator.width < 4 || self.generator.height < 3 {
+                return Err(format!("generated tracks must be at least 4 by 3 tiles, got {} by {}", self.generator.width, self.generator.height));
+            }
+            if !(0.0..=1.0).contains(&self.generator.difficulty) {
+                return Err(format!("`generator.difficulty` must be between 0 and 1, got {}", self.generator.difficulty));
+            }
+            Ok(())
+        };
+        check().map_err(GameError::Config)
+    }
+
+    pub fn car(&self, name: &str) -> Option<&CarConfig> {
+        self.cars.iter().find(|car| car.name == name)
+    }
+
+    // query parameters override the config file, e.g. `?mode=race&track=oval&cars=100&seed=42&server=...`
+    pub fn apply_query(&mut self, query: &str) -> Result<(), GameError> {
+        for (key, value) in parse_query(query) {
+            let invalid = |reason: &str| GameError::Config(format!("query parameter `{}={}`: {}", key, value, reason));
+            match key.as_str() {
+                "mode" => {
+                    self.launch.mode = match value.as_str() {
+                        "menu" => LaunchMode::Menu,
+                        "race" => LaunchMode::Race,
+                        "training" | "train" => LaunchMode::Training,
+                        _ => return Err(invalid("expected menu, race or training")),
+                    };
+                },
+                "track" => self.launch.track = Some(value.clone()),
+                "car" => self.race.player_car = value.clone(),
+                "laps" => self.race.laps = value.parse().map_err(|_| invalid("expected a whole number"))?,
+                "cars" => self.training.num_cars = value.parse().map_err(|_| invalid("expected a whole number"))?,
+                "seed" => self.seed = Some(value.parse().map_err(|_| invalid("expected a whole number"))?),
+                "server" => self.server_url = value.clone(),
+                "generate" => self.generator.tracks = value.parse().map_err(|_| invalid("expected a whole number"))?,
+                "difficulty" => self.generator.difficulty = value.parse().map_err(|_| invalid("expected a number"))?,
+                _ => crate::log_warn!("ignoring unknown query parameter `{}`", key),
+            }
+        }
+        self.validate()
+    }
+
+    // a missing config file falls back to the defaults, an invalid one is an error
+    pub async fn load(url: &str) -> Result<Self, GameError> {
+        match get(url).await {
+            Ok(text) => Self::from_json(&text.as_string().unwrap_or_default()),
+            Err(err) => {
+                crate::log_warn!("could not fetch {}, using default config: {:?}", url, err);
+                Ok(GameConfig::default())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        assert_eq!(GameConfig::default(), GameConfig::from_json("{}").unwrap());
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config = GameConfig::from_json(r#"{ "training": { "num_cars": 100 } }"#).unwrap();
+        assert_eq!(100, config.training.num_cars);
+        assert_eq!(0.3, config.training.evaluate_interval, "unset fields should keep defaults");
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let err = GameConfig::from_json(r#"{ "race": { "player": "no5" } }"#).unwrap_err();
+        assert!(err.to_string().contains("player"), "should name the unknown field: {}", err);
+    }
+
+    #[test]
+    fn test_query_overrides() {
+        let mut config = GameConfig::default();
+        config.apply_query("?mode=race&track=oval&cars=100&laps=5&seed=42&server=http%3A%2F%2Flocalhost%3A8080").unwrap();
+        assert_eq!(LaunchMode::Race, config.launch.mode);
+        assert_eq!(Some("oval".to_string()), config.launch.track);
+        assert_eq!(100, config.training.num_cars);
+        assert_eq!(5, config.race.laps);
+        assert_eq!(Some(42), config.seed);
+        assert_eq!("http://localhost:8080", config.server_url);
+    }
+
+    #[test]
+    fn test_invalid_query() {
+        let err = GameConfig::default().apply_query("cars=many").unwrap_err();
+        assert!(err.to_string().contains("cars=many"), "should name the parameter: {}", err);
+        assert!(GameConfig::default().apply_query("cars=0").is_err(), "overrides should be validated");
+        assert!(GameConfig::default().apply_query("mode=fly").is_err());
+        assert!(GameConfig::default().apply_query("difficulty=2").is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!("a b/c%", percent_decode("a+b%2Fc%"));
+    }
+
+    #[test]
+    fn test_validation() {
+        let err = GameConfig::from_json(r#"{ "cars": [{ "name": "slow", "sensor_range": 0.0 }], "race": { "player_car": "slow" }, "training": { "car_classes": ["slow"] } }"#).unwrap_err();
+        assert!(err.to_string().contains("cars[slow].sensor_range"), "should name the invalid field: {}", err);
+        assert!(GameConfig::from_json(r#"{ "server_url": "localhost:3030" }"#).is_err());
+    }
+
+    #[test]
+    fn test_car_classes() {
+        let config = GameConfig::from_json(r#"{
+            "cars": [
+                { "name": "truck", "sprite": "car_5.png", "mass": 3.0, "engine_force": 600.0 },
+                { "name": "kart", "width": 40.0, "height": 24.0 }
+            ],
+            "race": { "player_car": "kart", "opponents": ["truck"] },
+            "training": { "car_classes": ["kart", "truck"] }
+        }"#).unwrap();
+        assert_eq!(3.0, config.car("truck").unwrap().mass);
+        assert_eq!(200.0, config.car("kart").unwrap().sensor_range, "unset fields should keep defaults");
+        assert!(config.car("no8").is_none(), "a cars list replaces the default cars");
+    }
+
+    #[test]
+    fn test_unknown_car_class() {
+        let err = GameConfig::from_json(r#"{ "race": { "player_car": "bus" } }"#).unwrap_err();
+        assert!(err.to_string().contains("bus"), "should name the missing car: {}", err);
+        let err = GameConfig::from_json(r#"{ "cars": [{ "name": "a" }, { "name": "a" }] }"#).unwrap_err();
+        assert!(err.to_string().contains("more than once"), "{}", err);
+        assert!(GameConfig::default().apply_query("car=no5").is_ok());
+        assert!(GameConfig::default().apply_query("car=bus").is_err());
+    }
+}
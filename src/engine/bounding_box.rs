@@ -30,6 +30,23 @@ impl BoundingBox {
         return bounding_box;
     }
 
+    // a four sided body that is not a rectangle, only for bodies that never move
+    pub fn from_quad(points: [FVec; 4]) -> Self {
+        let (mut min, mut max) = (points[0], points[0]);
+        for point in points.iter() {
+            min = FVec::new(min.x.min(point.x), min.y.min(point.y));
+            max = FVec::new(max.x.max(point.x), max.y.max(point.y));
+        }
+        let lines = (0..4).map(|i| Line { start: points[i], end: points[(i + 1) % 4] }).collect();
+        BoundingBox {
+            rect: Rect { x: min.x, y: min.y, w: max.x - min.x, h: max.y - min.y },
+            lines: Some(lines),
+            points: Some(points.to_vec()),
+            rotate: 0.0,
+            origin: FVec::default(),
+        }
+    }
+
     pub fn contains(&self, point: &FVec) -> bool {
         let mut count = 0;
         if let Some(ref points) = self.points {
@@ -65,6 +82,12 @@ impl BoundingBox {
         self.update_coordinates();
     }
 
+    pub fn move_by(&mut self, offset: &FVec) {
+        self.rect.x += offset.x;
+        self.rect.y += offset.y;
+        self.update_coordinates();
+    }
+
     fn update_coordinates(&mut self) {
         let mut coordinates = vec![];
         let mut lines = vec![];
@@ -109,10 +132,44 @@ impl BoundingBox {
         None
     }
 
+    pub fn collide(&self, other: &BoundingBox) -> Option<Contact> {
+        let (lines, other_lines) = match (&self.lines, &other.lines) {
+            (Some(lines), Some(other_lines)) => (lines, other_lines),
+            _ => return None,
+        };
+        let point = lines.iter().find_map(|line| other_lines.iter().find_map(|other_line| line.intersect(other_line)))?;
+        // push apart along the line between the centers
+        let normal = match (self.get_center(), other.get_center()) {
+            (Some(center), Some(other_center)) if center.distance(&other_center) > 0.0 => {
+                let distance = center.distance(&other_center);
+                FVec::new((center.x - other_center.x) / distance, (center.y - other_center.y) / distance)
+            },
+            _ => FVec::default(),
+        };
+        Some(Contact { point, normal })
+    }
+
+    // unlike `collide` this also catches one box lying entirely inside the other
+    pub fn overlaps(&self, other: &BoundingBox) -> bool {
+        if self.collide(other).is_some() {
+            return true;
+        }
+        let inside = |outer: &BoundingBox, inner: &BoundingBox| inner.get_center().map_or(false, |center| outer.contains(&center));
+        inside(self, other) || inside(other, self)
+    }
+
     pub fn debug_view(&self, renderer: &Renderer) {
-        let (coordinates, lines) = self.get_coordinates();
-        renderer.save();
-        renderer.restore();
+        let (coordinates, _) = self.get_coordinates();
+        if let Some(points) = coordinates {
+            renderer.save();
+            renderer.stroke_style("red");
+            renderer.polygon(points, false);
+            if let Some(center) = self.get_center() {
+                renderer.fill_style("red");
+                renderer.arc(center.x, center.y, 2.0, 0.0, PI * 2.0, true);
+            }
+            renderer.restore();
+        }
     }
 }
 
@@ -132,4 +189,23 @@ mod tests {
         assert_eq!(true, bounding_box.contains(&FVec { x: 0.5, y: 0.5 }), "not working");
         assert_eq!(false, bounding_box.contains(&FVec { x: 1.5, y: 1.5 }), "not working");
     }
+
+    #[test]
+    fn test_quad() {
+        let quad = BoundingBox::from_quad([FVec::new(0.0, 0.0), FVec::new(10.0, 0.0), FVec::new(14.0, 10.0), FVec::new(-4.0, 10.0)]);
+        assert_eq!(Rect { x: -4.0, y: 0.0, w: 18.0, h: 10.0 }, quad.rect);
+        assert!(quad.contains(&FVec::new(12.0, 9.0)), "outside the top edge but inside the wider bottom");
+        assert!(!quad.contains(&FVec::new(12.0, 1.0)));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let car = BoundingBox::new(&Rect { x: 0.0, y: 0.0, w: 60.0, h: 40.0 });
+        let inside = BoundingBox::new(&Rect { x: 20.0, y: 10.0, w: 10.0, h: 10.0 });
+        let crossing = BoundingBox::new(&Rect { x: 50.0, y: 10.0, w: 20.0, h: 10.0 });
+        let apart = BoundingBox::new(&Rect { x: 100.0, y: 10.0, w: 10.0, h: 10.0 });
+        assert!(car.overlaps(&inside) && inside.overlaps(&car), "containment should overlap both ways");
+        assert!(car.overlaps(&crossing));
+        assert!(!car.overlaps(&apart));
+    }
 }
@@ -2,22 +2,28 @@ use std::collections::*;
 use futures::channel::mpsc::*;
 use web_sys::*;
 use crate::browser::*;
+use super::FVec;
 use std::rc::Rc;
 use std::cell::RefCell;
 
 #[derive(Debug)]
 pub struct KeyboardState {
     pressed_keys: HashMap<String, KeyboardEvent>,
+    just_pressed: HashSet<String>,
 }
 
 impl KeyboardState {
     pub fn new() -> Self {
         KeyboardState {
-            pressed_keys: HashMap::new()
+            pressed_keys: HashMap::new(),
+            just_pressed: HashSet::new(),
         }
     }
 
     pub fn press(&mut self, event: KeyboardEvent) {
+        if !self.pressed_keys.contains_key(&event.code()) {
+            self.just_pressed.insert(event.code());
+        }
         self.pressed_keys.insert(event.code(), event);
     }
 
@@ -25,11 +31,83 @@ impl KeyboardState {
         self.pressed_keys.contains_key(code)
     }
 
+    // true only on the tick the key went down, key repeats are ignored
+    pub fn was_pressed(&self, code: &str) -> bool {
+        self.just_pressed.contains(code)
+    }
+
+    // some key that went down this tick
+    pub fn any_pressed(&self) -> Option<&str> {
+        self.just_pressed.iter().next().map(|code| code.as_str())
+    }
+
+    // key up events are lost while the window is not focused
+    pub fn release_all(&mut self) {
+        self.pressed_keys.clear();
+        self.just_pressed.clear();
+    }
+
+    pub fn clear_just_pressed(&mut self) {
+        self.just_pressed.clear();
+    }
+
     pub fn set_released(&mut self, code: &str) {
         self.pressed_keys.remove(code);
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseButton {
+    Primary,
+    Secondary,
+}
+
+#[derive(Debug, Default)]
+pub struct MouseState {
+    position: Option<FVec>,
+    clicks: Vec<(FVec, MouseButton)>,
+}
+
+impl MouseState {
+    pub fn new() -> Self {
+        MouseState::default()
+    }
+
+    // None until the mouse has moved over the canvas
+    pub fn position(&self) -> Option<FVec> {
+        self.position
+    }
+
+    // the clicks since the last tick
+    pub fn clicks(&self) -> &[(FVec, MouseButton)] {
+        &self.clicks
+    }
+
+    pub fn clear_clicks(&mut self) {
+        self.clicks.clear();
+    }
+}
+
+pub fn process_mouse_input(receiver: &mut UnboundedReceiver<MouseInput>, state: Rc<RefCell<MouseState>>) {
+    while let Ok(Some(event)) = receiver.try_next() {
+        let mut state = state.borrow_mut();
+        match event {
+            MouseInput::Down { x, y, button } => {
+                let button = match button {
+                    0 => MouseButton::Primary,
+                    2 => MouseButton::Secondary,
+                    _ => continue,
+                };
+                state.position = Some(FVec::new(x, y));
+                state.clicks.push((FVec::new(x, y), button));
+            },
+            MouseInput::Move { x, y } => {
+                state.position = Some(FVec::new(x, y));
+            },
+        }
+    }
+}
+
 pub fn process_input(receiver: &mut UnboundedReceiver<KeyPress>, state: Rc<RefCell<KeyboardState>>) {
     loop {
         match receiver.try_next() {
@@ -1,14 +1,17 @@
 use wasm_bindgen::closure::*;
 use std::{rc::Rc, cell::*};
 use web_sys::{ Window, HtmlCanvasElement, CanvasRenderingContext2d };
+use futures::channel::mpsc::*;
 use crate::browser::*;
 use crate::game::*;
+use crate::error::GameError;
 use wasm_bindgen::{ JsValue, JsCast };
 mod input;
 mod bounding_box;
 mod vec;
 mod renderer;
 pub mod network;
+pub mod profiler;
 pub use input::*;
 pub use bounding_box::*;
 pub use vec::*;
@@ -61,6 +64,33 @@ impl Line {
         }
         None
     }
+
+    pub fn distance_to_point(&self, point: &FVec) -> f64 {
+        let (dx, dy) = (self.end.x - self.start.x, self.end.y - self.start.y);
+        let length_sq = dx * dx + dy * dy;
+        if length_sq == 0.0 {
+            return self.start.distance(point);
+        }
+        let t = (((point.x - self.start.x) * dx + (point.y - self.start.y) * dy) / length_sq).max(0.0).min(1.0);
+        point.distance(&FVec::new(self.start.x + dx * t, self.start.y + dy * t))
+    }
+
+    // unit vector perpendicular to the line, zero for a degenerate line
+    pub fn normal(&self) -> FVec {
+        let (dx, dy) = (self.end.x - self.start.x, self.end.y - self.start.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return FVec::default();
+        }
+        FVec::new(-dy / length, dx / length)
+    }
+}
+
+// where two bodies touch, `normal` is a unit vector, its sign is arbitrary
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Contact {
+    pub point: FVec,
+    pub normal: FVec,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -95,6 +125,8 @@ pub struct RenderLoop {
 }
 
 const FRAME_RATE: f64 = 1.0 / 60.0 * 1000.0;
+// after a long stall drop the backlog instead of fast forwarding through it
+const MAX_FRAMES_BEHIND: f64 = 10.0;
 
 impl RenderLoop {
     pub fn new() -> Self {
@@ -105,28 +137,41 @@ impl RenderLoop {
         }
     }
 
-    fn add_global_listeners() -> Result<(), JsValue> {
+    fn add_global_listeners() -> Result<UnboundedReceiver<WindowEvent>, JsValue> {
+        let (blur_sender, receiver) = unbounded();
+        let blur_sender = Rc::new(RefCell::new(blur_sender));
+        let visibility_sender = Rc::clone(&blur_sender);
         let window: Window = crate::browser::window();
-        let listener: Closure<dyn FnMut(web_sys::FocusEvent)> = Closure::new(move | event | {
-            crate::console_log!("got event {:?}", event);
+        let on_blur: Closure<dyn FnMut(web_sys::FocusEvent)> = Closure::new(move | _event | {
+            blur_sender.borrow_mut().start_send(WindowEvent::Blur);
         });
-        window.add_event_listener_with_callback("blur", listener.as_ref().unchecked_ref())?;
-        listener.forget();
-        Ok(())
+        window.add_event_listener_with_callback("blur", on_blur.as_ref().unchecked_ref())?;
+        let on_visibility_change: Closure<dyn FnMut()> = Closure::new(move || {
+            let event = if document().hidden() { WindowEvent::Hidden } else { WindowEvent::Visible };
+            visibility_sender.borrow_mut().start_send(event);
+        });
+        document().add_event_listener_with_callback("visibilitychange", on_visibility_change.as_ref().unchecked_ref())?;
+        on_blur.forget();
+        on_visibility_change.forget();
+        Ok(receiver)
     }
 
-    pub async fn start(mut self, mut game: RacingGame, canvas: HtmlCanvasElement) -> Result<(), JsValue> {
-        let context = get_context(&canvas).expect("there is no context");
+    pub async fn start(mut self, mut game: RacingGame, canvas: HtmlCanvasElement) -> Result<(), GameError> {
+        let context = get_context(&canvas)?;
         let renderer = Renderer{ context };
+        let mut window_events = Self::add_global_listeners()?;
         self.last_frame = now();
         let f: SharedLoopClosure = Rc::new(RefCell::new(None));
         let g = f.clone();
         *g.borrow_mut() = Some(Closure::wrap(Box::new(move |perf: f64| {
-            let mut delta = perf - self.last_frame;
-            self.accumulated_delta += delta;
-            while self.accumulated_delta > FRAME_RATE {
-                game.update(delta / 1000.0);
-                delta = self.accumulated_delta - FRAME_RATE;
+            while let Ok(Some(event)) = window_events.try_next() {
+                game.on_window_event(event);
+            }
+            let delta = perf - self.last_frame;
+            self.accumulated_delta = (self.accumulated_delta + delta).min(FRAME_RATE * MAX_FRAMES_BEHIND);
+            // the game always advances in whole ticks of the same length
+            while self.accumulated_delta >= FRAME_RATE {
+                game.update(FRAME_RATE / 1000.0);
                 self.accumulated_delta -= FRAME_RATE;
             }
             self.last_frame = perf;
@@ -139,6 +184,9 @@ impl RenderLoop {
 }
 
 
-fn get_context(canvas: &HtmlCanvasElement) -> Option<CanvasRenderingContext2d> {
-    canvas.get_context("2d").unwrap().unwrap().dyn_into::<CanvasRenderingContext2d>().ok()
+pub fn get_context(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d, GameError> {
+    canvas.get_context("2d")?
+        .ok_or(GameError::NoContext)?
+        .dyn_into::<CanvasRenderingContext2d>()
+        .map_err(|_| GameError::NoContext)
 }
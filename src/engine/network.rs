@@ -7,6 +7,22 @@ use web_sys::RequestMode;
 use web_sys::Response;
 use web_sys::Headers;
 
+pub async fn get(url: &str) -> Result<JsValue, JsValue> {
+    let mut opts = RequestInit::new();
+    opts.method("GET");
+    let request = Request::new_with_str_and_init(url, &opts)?;
+    let window = web_sys::window().expect("no global `window` exists");
+    let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+
+    let resp: Response = resp_value.dyn_into()?;
+    if !resp.ok() {
+        return Err(JsValue::from_str(&format!("GET {} failed with status {}", url, resp.status())));
+    }
+    let text = JsFuture::from(resp.text()?).await?;
+
+    Ok(text)
+}
+
 pub async fn post(url: &str, data: Option<&str>) -> Result<JsValue, JsValue> {
     let mut opts = RequestInit::new();
     opts.method("POST");
@@ -22,6 +38,9 @@ pub async fn post(url: &str, data: Option<&str>) -> Result<JsValue, JsValue> {
     let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
 
     let resp: Response = resp_value.dyn_into()?;
+    if !resp.ok() {
+        return Err(JsValue::from_str(&format!("POST {} failed with status {}", url, resp.status())));
+    }
     let text = JsFuture::from(resp.text()?).await?;
 
     Ok(text)
@@ -0,0 +1,155 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use serde::Serialize;
+use crate::browser::now;
+
+const WINDOW_SIZE: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Phase {
+    Input,
+    Sensing,
+    Collision,
+    Scoring,
+    Evaluate,
+    Drawing,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [Phase::Input, Phase::Sensing, Phase::Collision, Phase::Scoring, Phase::Evaluate, Phase::Drawing];
+}
+
+#[derive(Debug, Default)]
+pub struct RollingAverage {
+    samples: VecDeque<f64>,
+    sum: f64,
+}
+
+impl RollingAverage {
+    pub fn push(&mut self, sample: f64) {
+        self.samples.push_back(sample);
+        self.sum += sample;
+        if self.samples.len() > WINDOW_SIZE {
+            if let Some(oldest) = self.samples.pop_front() {
+                self.sum -= oldest;
+            }
+        }
+    }
+
+    pub fn average(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.sum / self.samples.len() as f64
+    }
+
+    pub fn max(&self) -> f64 {
+        self.samples.iter().cloned().fold(0.0, f64::max)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    pub average_ms: f64,
+    pub max_ms: f64,
+}
+
+// phases are accumulated over a whole frame (all ticks and all cars) before they are averaged
+#[derive(Debug, Default)]
+pub struct Profiler {
+    enabled: bool,
+    current: [f64; 6],
+    averages: [RollingAverage; 6],
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    pub fn record(&mut self, phase: Phase, ellapsed: f64) {
+        self.current[phase as usize] += ellapsed;
+    }
+
+    pub fn end_frame(&mut self) {
+        for (index, average) in self.averages.iter_mut().enumerate() {
+            average.push(self.current[index]);
+            self.current[index] = 0.0;
+        }
+    }
+
+    pub fn report(&self) -> Vec<PhaseReport> {
+        Phase::ALL.iter().map(|phase| {
+            let average = &self.averages[*phase as usize];
+            PhaseReport { phase: *phase, average_ms: average.average(), max_ms: average.max() }
+        }).collect()
+    }
+}
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::new());
+}
+
+pub fn set_enabled(enabled: bool) {
+    PROFILER.with(|profiler| profiler.borrow_mut().enabled = enabled);
+}
+
+pub fn is_enabled() -> bool {
+    PROFILER.with(|profiler| profiler.borrow().enabled)
+}
+
+pub fn profile<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !is_enabled() {
+        return f();
+    }
+    let start = now();
+    let result = f();
+    let ellapsed = now() - start;
+    PROFILER.with(|profiler| profiler.borrow_mut().record(phase, ellapsed));
+    result
+}
+
+pub fn end_frame() {
+    PROFILER.with(|profiler| {
+        let mut profiler = profiler.borrow_mut();
+        if profiler.enabled {
+            profiler.end_frame();
+        }
+    });
+}
+
+pub fn report() -> Vec<PhaseReport> {
+    PROFILER.with(|profiler| profiler.borrow().report())
+}
+
+pub fn report_json() -> String {
+    serde_json::to_string(&report()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_average_window() {
+        let mut average = RollingAverage::default();
+        for _ in 0..WINDOW_SIZE {
+            average.push(1.0);
+        }
+        average.push(61.0);
+        assert_eq!(2.0, average.average(), "oldest sample should be dropped");
+        assert_eq!(61.0, average.max());
+    }
+
+    #[test]
+    fn test_profiler_accumulates_per_frame() {
+        let mut profiler = Profiler::new();
+        profiler.record(Phase::Sensing, 1.0);
+        profiler.record(Phase::Sensing, 2.0);
+        profiler.end_frame();
+        let report = profiler.report();
+        assert_eq!(3.0, report[Phase::Sensing as usize].average_ms, "should sum samples within a frame");
+        assert_eq!(0.0, report[Phase::Drawing as usize].average_ms);
+    }
+}
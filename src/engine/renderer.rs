@@ -5,6 +5,21 @@ pub struct Renderer {
     pub context: CanvasRenderingContext2d,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TextBaseline {
+    Top,
+    Middle,
+    Alphabetic,
+    Bottom,
+}
+
 impl Renderer {
     pub fn clear(&self, rect: &Rect) {
         self.context.clear_rect(rect.x, rect.y, rect.w, rect.h);
@@ -32,6 +47,7 @@ impl Renderer {
     }
 
     pub fn line(&self, line: &Line) {
+        self.context.begin_path();
         self.context.move_to(line.start.x, line.start.y);
         self.context.line_to(line.end.x, line.end.y);
         self.context.stroke();
@@ -41,10 +57,85 @@ impl Renderer {
         self.context.stroke_text(text, position.x, position.y);
     }
 
+    pub fn fill_text(&self, text: &str, position: FVec) {
+        let _ = self.context.fill_text(text, position.x, position.y);
+    }
+
+    pub fn measure_text(&self, text: &str) -> f64 {
+        self.context.measure_text(text).map(|metrics| metrics.width()).unwrap_or(0.0)
+    }
+
+    pub fn font(&self, font: &str) {
+        self.context.set_font(font);
+    }
+
+    pub fn text_align(&self, align: TextAlign) {
+        self.context.set_text_align(match align {
+            TextAlign::Left => "left",
+            TextAlign::Center => "center",
+            TextAlign::Right => "right",
+        });
+    }
+
+    pub fn text_baseline(&self, baseline: TextBaseline) {
+        self.context.set_text_baseline(match baseline {
+            TextBaseline::Top => "top",
+            TextBaseline::Middle => "middle",
+            TextBaseline::Alphabetic => "alphabetic",
+            TextBaseline::Bottom => "bottom",
+        });
+    }
+
     pub fn stroke_style(&self, style: &str) {
         self.context.set_stroke_style(&JsValue::from_str(style));
     }
 
+    pub fn fill_style(&self, style: &str) {
+        self.context.set_fill_style(&JsValue::from_str(style));
+    }
+
+    pub fn line_width(&self, width: f64) {
+        self.context.set_line_width(width);
+    }
+
+    pub fn global_alpha(&self, alpha: f64) {
+        self.context.set_global_alpha(alpha.clamp(0.0, 1.0));
+    }
+
+    pub fn rounded_rect(&self, rect: &Rect, radius: f64, fill: bool) {
+        let radius = radius.min(rect.w / 2.0).min(rect.h / 2.0).max(0.0);
+        let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
+        self.context.begin_path();
+        self.context.move_to(x + radius, y);
+        let _ = self.context.arc_to(x + w, y, x + w, y + h, radius);
+        let _ = self.context.arc_to(x + w, y + h, x, y + h, radius);
+        let _ = self.context.arc_to(x, y + h, x, y, radius);
+        let _ = self.context.arc_to(x, y, x + w, y, radius);
+        self.context.close_path();
+        if fill {
+            self.context.fill();
+        } else {
+            self.context.stroke();
+        }
+    }
+
+    pub fn polygon(&self, points: &[FVec], fill: bool) {
+        if points.len() < 2 {
+            return;
+        }
+        self.context.begin_path();
+        self.context.move_to(points[0].x, points[0].y);
+        for point in &points[1..] {
+            self.context.line_to(point.x, point.y);
+        }
+        self.context.close_path();
+        if fill {
+            self.context.fill();
+        } else {
+            self.context.stroke();
+        }
+    }
+
 
     pub fn arc(&self, x: f64, y:f64, radius:f64, start_angle:f64, end_angle:f64, fill: bool) {
         self.context.begin_path();
@@ -0,0 +1,89 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameError {
+    AssetLoad { src: String },
+    ElementNotFound(String),
+    NoContext,
+    Config(String),
+    InvalidTrack { name: String, issues: Vec<TrackIssue> },
+    Storage(String),
+    Js(String),
+}
+
+// a problem found while validating a track, `segment` is the index in the track file
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackIssue {
+    Parse(String),
+    Empty,
+    UnknownTile { segment: usize, tile: String },
+    Gap { segment: usize },
+    Overlap { segment: usize, other: usize },
+    MissingSegment { field: String, segment: usize },
+    PitLaneNotStraight { segment: usize },
+    NoStart,
+    NotClosed,
+    ClosingGap,
+    // a track is either tiles or a curve through control points
+    MixedLayout,
+    NotTiled,
+    TooFewPoints,
+    InvalidPoint { point: usize },
+    InvalidResolution,
+    TooTight { point: usize },
+}
+
+impl fmt::Display for TrackIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackIssue::Parse(message) => write!(f, "{}", message),
+            TrackIssue::Empty => write!(f, "the track has no segments"),
+            TrackIssue::UnknownTile { segment, tile } => write!(f, "segment {}: unknown tile `{}`", segment, tile),
+            TrackIssue::Gap { segment } => write!(f, "segment {} does not continue from segment {}", segment, segment - 1),
+            TrackIssue::Overlap { segment, other } => write!(f, "segment {} overlaps segment {}", segment, other),
+            TrackIssue::MissingSegment { field, segment } => write!(f, "`{}` refers to segment {} which does not exist", field, segment),
+            TrackIssue::PitLaneNotStraight { segment } => write!(f, "pit lane segment {} is not a straight", segment),
+            TrackIssue::NoStart => write!(f, "the track has no start line"),
+            TrackIssue::NotClosed => write!(f, "the track does not lead back to its start"),
+            TrackIssue::ClosingGap => write!(f, "segment 0 does not continue from the last segment"),
+            TrackIssue::MixedLayout => write!(f, "the track has both tile segments and a centerline"),
+            TrackIssue::NotTiled => write!(f, "only tile tracks can be edited"),
+            TrackIssue::TooFewPoints => write!(f, "the centerline needs at least 3 points"),
+            TrackIssue::InvalidPoint { point } => write!(f, "centerline point {} needs finite coordinates and a positive width", point),
+            TrackIssue::InvalidResolution => write!(f, "`resolution` must be a positive number"),
+            TrackIssue::TooTight { point } => write!(f, "the bend after centerline point {} is too tight for the width of the road", point),
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::AssetLoad { src } => write!(f, "failed to load asset `{}`", src),
+            GameError::ElementNotFound(id) => write!(f, "did not find element `#{}`", id),
+            GameError::NoContext => write!(f, "canvas has no 2d context"),
+            GameError::Config(message) => write!(f, "invalid config: {}", message),
+            GameError::InvalidTrack { name, issues } => {
+                let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+                write!(f, "invalid track `{}`: {}", name, issues.join("; "))
+            },
+            GameError::Storage(message) => write!(f, "storage error: {}", message),
+            GameError::Js(message) => write!(f, "javascript error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<JsValue> for GameError {
+    fn from(value: JsValue) -> Self {
+        GameError::Js(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+    }
+}
+
+impl From<GameError> for JsValue {
+    fn from(error: GameError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
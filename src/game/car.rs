@@ -1,17 +1,20 @@
 use crate::{engine::*, browser::load_image};
+use crate::engine::profiler::{profile, Phase};
 use web_sys::*;
 use super::*;
 use super::car_controller::*;
 use super::car_sensor::*;
 use super::score::Score;
+use super::lap::LapTimer;
 use std::hash::{Hash, Hasher};
 use uuid::Uuid;
-
-#[derive(Debug)]
-pub struct Action<T>(T);
-
-const ACCELARATE: f64 = 10.0;
-const TURNING_ANGLE: f64 = 3.0;
+use crate::error::GameError;
+use crate::config::{CarConfig, SpawnConfig};
+use super::vehicle::*;
+use super::surface::Surface;
+use super::damage::Damage;
+use super::consumables::Consumables;
+use super::pickup::{Effects, PickupKind};
 
 #[derive(PartialEq, Debug)]
 pub enum CarStatus {
@@ -19,28 +22,26 @@ pub enum CarStatus {
     Dead,
 }
 
-pub enum CarType {
-    No5,
-    No8
-}
-
 #[derive(Debug)]
 pub struct Car {
     pub id: uuid::Uuid,
+    pub class: String,
     pub body: BoundingBox,
     pub status: CarStatus,
-    pub turning_angle: f64,
     pub sensor: Sensor,
-    pub velocity: f64,
+    pub state: VehicleState,
+    pub input: ControlInput,
+    pub surface: Surface,
+    pub damage: Damage,
+    pub consumables: Consumables,
+    pub effects: Effects,
     pub score: score::Score,
+    pub laps: LapTimer,
     image: HtmlImageElement,
-    actions: Vec<Action<Movement>>,
+    params: VehicleParams,
     controller: Box<dyn CarController>,
 }
 
-const CAR_WIDTH: f64 = 63.0;
-const CAR_HEIGHT: f64 = 38.0;
-
 #[derive(Debug)]
 pub enum Movement {
     NotTurning,
@@ -52,99 +53,99 @@ pub enum Movement {
 }
 
 impl Car {
-    pub async fn new(id: Uuid, x: f64, y: f64, car_type: CarType, controller: Box<dyn CarController + 'static>) -> Self {
-        let image_src = match car_type {
-            CarType::No5 => "car_5.png",
-            CarType::No8 => "car_8.png",
-        };
-        let image = load_image(image_src).await.unwrap();
-        Car {
+    pub async fn new(id: Uuid, spec: &CarConfig, spawn: &SpawnConfig, controller: Box<dyn CarController + 'static>) -> Result<Self, GameError> {
+        let image = load_image(&spec.sprite).await?;
+        let (x, y) = (spawn.x, spawn.y);
+        let mut body = BoundingBox::new_with_origin(&Rect { x, y, w: spec.width, h: spec.height }, FVec { x: 0.0, y: spec.height / 2.0 });
+        body.reset_to(&FVec::new(x, y), spawn.rotate);
+        Ok(Car {
             id,
+            class: spec.name.clone(),
             image,
             status: CarStatus::Live,
-            actions: vec![],
-            sensor: Sensor::new(),
-            turning_angle: 0.0,
-            velocity: 0.0,
-            body: BoundingBox::new_with_origin(&Rect { x, y, w: CAR_WIDTH, h: CAR_HEIGHT }, FVec { x: 0.0, y: CAR_HEIGHT / 2.0 }),
+            sensor: Sensor::new(spec.sensor_range),
+            state: VehicleState::default(),
+            input: ControlInput::default(),
+            surface: Surface::Asphalt,
+            damage: Damage::new(),
+            consumables: Consumables::new(spec),
+            effects: Effects::new(),
+            body,
             score: Score::new(),
+            laps: LapTimer::new(),
+            params: VehicleParams::from_config(spec),
             controller,
-        }
+        })
     }
 
     pub fn reset(&mut self, point: &FVec, rotate: f64) {
         self.status = CarStatus::Live;
-        self.velocity = 0.0;
-        self.turning_angle = 0.0;
+        self.state = VehicleState::default();
+        self.input = ControlInput::default();
+        self.damage.reset();
+        self.consumables.reset();
+        self.effects.reset();
         self.body.reset_to(point, rotate);
         self.score.reset();
+        self.laps.reset();
     }
 
-    fn process_actions(&mut self, delta: f64) {
-        for i in 0..self.actions.len() {
-            match self.actions[i].0 {
-                Movement::Left => {
-                    self.turning_angle = -TURNING_ANGLE;
-                },
-                Movement::Right => {
-                    self.turning_angle = TURNING_ANGLE;
-                },
-                Movement::NotTurning => {
-                    self.turning_angle = 0.0;
-                },
-                Movement::Forward => {
-                    self.calculate_pos(ACCELARATE, delta)
-                },
-                Movement::Backward => {
-                    self.calculate_pos(-ACCELARATE * 2.0,delta)
-                },
-                Movement::NotAccelarate => {
-                    self.calculate_pos(0.0, delta)
-                }
-            }
-        }
-        self.actions.clear();
+    pub fn velocity(&self) -> f64 {
+        self.state.velocity_long
     }
 
-    fn calculate_pos(&mut self, accelarating: f64, delta: f64) {
-        if self.velocity.abs() > 2.0 {
-            if self.velocity < 0.0 {
-                self.body.turn_at(-self.turning_angle * delta);
-            } else {
-                self.body.turn_at(self.turning_angle * delta);
-            }
-        }
-        self.velocity += self.cal_accelate(accelarating);
-        if accelarating == 0.0 && self.velocity.abs() < 0.2 {
-            self.velocity = 0.0;
+    fn calculate_pos(&mut self, delta: f64) {
+        let params = self.effects.apply_to(&self.params)
+            .fueled(self.consumables.fuel)
+            .worn(self.consumables.grip_factor())
+            .damaged(self.damage.amount)
+            .on_surface(&self.surface.properties());
+        let mut input = self.input;
+        if self.consumables.pit_stop.is_some() {
+            input = ControlInput::default();
+        } else if self.consumables.is_empty() {
+            input.throttle = 0.0;
         }
-        self.body.move_at(self.velocity * delta)
+        let motion = self.state.step(&params, &input, self.body.rotate, delta);
+        let distance = (motion.offset.x * motion.offset.x + motion.offset.y * motion.offset.y).sqrt();
+        self.consumables.drive(distance, input.throttle);
+        self.body.turn_at(motion.rotate);
+        self.body.move_by(&motion.offset);
     }
 
-    fn cal_accelate(&self, acc: f64) -> f64 {
-        let mut friction = 0.0;
-        if self.velocity != 0.0 {
-            friction = self.velocity / 50.0;
-            if self.velocity.abs() < 10.0 {
-                friction *= 50.0;
-            }
+    // crossing a boundary only ends the run when `fatal`, otherwise hitting it hard damages the car
+    fn process_collision(&mut self, track: &track::Track, fatal: bool) {
+        if let Some(center) = self.body.get_center() {
+            self.surface = track.surface_at(&center);
         }
-        return acc - friction;
-    }
-
-    fn process_collision(&mut self, track: &track::Track) {
-        if let Some((collision, track_type)) = track.on_collide(self) {
-            match track_type {
-                track::TrackSegmentType::FinishLine => {},
-                _ => {
+        let collision = track.on_collide(self);
+        if fatal {
+            if let Some((_, track_type)) = collision {
+                if track_type != track::TrackSegmentType::FinishLine {
                     self.status = CarStatus::Dead;
                 }
             }
+            return;
         }
+        let velocity = self.state.world_velocity(self.body.rotate);
+        self.damage.wall_contact(collision.map(|(contact, _)| velocity.dot(&contact.normal)));
+    }
+
+    fn process_car_collisions(&mut self, others: &[&Car]) {
+        let velocity = self.state.world_velocity(self.body.rotate);
+        let contacts: Vec<(Uuid, f64)> = others.iter()
+            .filter(|other| other.status == CarStatus::Live)
+            .filter_map(|other| {
+                let contact = self.body.collide(&other.body)?;
+                let relative = velocity - other.state.world_velocity(other.body.rotate);
+                Some((other.id, relative.dot(&contact.normal)))
+            })
+            .collect();
+        self.damage.car_contacts(&contacts);
     }
 
     pub fn debug(&self) {
-        crate::console_log!("car is on dir {:?}", self.sensor.track_direction);
+        crate::log_trace!("car {} is on dir {:?}", self.id, self.sensor.track_direction);
     }
 
 }
@@ -171,31 +172,49 @@ impl GameObject for Car {
         renderer.translate(&FVec { x: -x - origin_x, y: -y - origin_y });
         renderer.draw_image_with_dest(&self.image, &Rect { x: x - origin_x, y: y - origin_y, w, h });
         renderer.restore();
-        self.debug();
     }
 
     fn update(&mut self, stage: &mut GameStage, delta: f64) {
         self.reset_sensor();
+        self.effects.tick(delta);
+        self.damage.shielded = self.effects.has(PickupKind::Shield);
         let stage = stage;
         let tracks = stage.find::<track::Track>();
         if !tracks.is_empty() {
             let track = tracks[0];
-            self.detect(track);
-            self.process_collision(track);
-            self.score.update(&self.body, track, delta);
-            if self.score.is_stale_for(2.0) {
+            profile(Phase::Sensing, || self.detect(track));
+            let fatal = stage.mode == GameMode::Training && stage.config.training.off_track_fatal;
+            profile(Phase::Collision, || self.process_collision(track, fatal));
+            // training cars are ghosts to each other, every car of a generation starts on the same spot
+            if stage.mode == GameMode::Race {
+                let others = stage.find::<Car>();
+                profile(Phase::Collision, || self.process_car_collisions(&others));
+            }
+            if self.damage.is_retired() && self.status == CarStatus::Live {
+                crate::log_debug!("car {} retired with damage", self.id);
                 self.status = CarStatus::Dead;
             }
-        }
-        match(self.status) {
-            CarStatus::Live => {
-                for movement in self.controller.next_movements(self) {
-                    self.actions.push(Action(movement));
+            profile(Phase::Scoring, || self.score.update(&self.body, track, self.consumables.pit_stop.is_some(), delta));
+            if let (CarStatus::Live, Some(center)) = (&self.status, self.body.get_center()) {
+                if let Some(time) = self.laps.update(center, &track.gates, delta) {
+                    crate::log_debug!("car {} finished lap {} in {:.3}s", self.id, self.laps.laps, time);
                 }
-            },
-            CarStatus::Dead => {},
+            }
+            let on_pit_lane = track.on_which_track_seg(&self.body).map_or(false, |seg| seg.track_type.is_pit_lane());
+            if self.consumables.update_pit(on_pit_lane, self.state.speed(), stage.config.race.pit_stop_time, delta) {
+                crate::log_debug!("car {} stopped in the pit lane", self.id);
+                self.state = VehicleState { pending: self.state.pending, ..VehicleState::default() };
+            }
+            if self.score.stalls_out(stage.mode, stage.config.training.stale_timeout) {
+                self.status = CarStatus::Dead;
+            }
+            self.debug();
         }
-        self.process_actions(delta);
+        self.input = match self.status {
+            CarStatus::Live => self.controller.next_input(self).clamped(),
+            CarStatus::Dead => ControlInput::default(),
+        };
+        self.calculate_pos(delta);
     }
 }
 
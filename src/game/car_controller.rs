@@ -5,23 +5,34 @@ use crate::engine::network::post;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use std::fmt::Debug as FmtDebug;
+use super::settings::KeyBindings;
+use super::vehicle::ControlInput;
+use crate::config::GameConfig;
+use crate::error::GameError;
+
+// the sensor, speed and direction inputs every network gets, the optional ones come after them
+const BASE_INPUTS: u32 = 7;
+// forward, right, backward, left and handbrake
+const NUM_OUTPUTS: u32 = 5;
 
 pub trait CarController: FmtDebug {
     fn get_id(&self) -> Uuid;
-    fn next_movements(&self, car: &Car) -> Vec<Movement>;
+    fn next_input(&self, car: &Car) -> ControlInput;
 }
 
 #[derive(Debug)]
 pub struct KeyController {
     id: Uuid,
-    keyboard_state: Rc<RefCell<KeyboardState>>
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    key_bindings: KeyBindings,
 }
 
 impl KeyController {
-    pub fn new(id: Uuid, keyboard_state: Rc<RefCell<KeyboardState>>) -> Self {
+    pub fn new(id: Uuid, keyboard_state: Rc<RefCell<KeyboardState>>, key_bindings: KeyBindings) -> Self {
         KeyController { 
             id,
-            keyboard_state 
+            keyboard_state,
+            key_bindings,
         }
     }
 }
@@ -60,51 +71,94 @@ impl Timer {
 
 #[derive(Serialize, Deserialize, Debug)]
 struct NewGenRequest {
+    population: String,
     num_of_cars: u32,
     num_of_args: u32,
     num_of_outputs: u32,
+    // keep the population the server already has for the class if it fits
+    keep: bool,
+    seed: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EvaluteRequest {
+    pub population: String,
     pub inputs: Vec<Vec<f64>>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SetFitnessesRequest {
+    pub population: String,
     pub fitnesses: Vec<f64>
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NextGenRequest {
+    pub population: String,
+}
+
 #[derive(Debug)]
 pub struct AutoDrive {
+    server_url: String,
+    // the car class this population drives, the server evolves each one separately
+    population: String,
+    discrete_outputs: bool,
+    // seeds the server's population so a training run can be repeated
+    seed: Option<u64>,
+    damage_input: bool,
+    strategy_inputs: bool,
     controllers: Rc<RefCell<HashMap<Uuid, Vec<f64>>>>,
     timer: Timer
 }
 
 impl AutoDrive {
-    pub fn new(update_interval: f64) -> Self {
+    pub fn new(config: &GameConfig, population: &str) -> Self {
         AutoDrive { 
-            timer: Timer::new(update_interval),
+            server_url: config.server_url.clone(),
+            population: population.to_string(),
+            discrete_outputs: config.training.discrete_outputs,
+            seed: config.seed,
+            damage_input: config.training.damage_input,
+            strategy_inputs: config.training.strategy_inputs,
+            timer: Timer::new(config.training.evaluate_interval),
             controllers: Rc::new(RefCell::new(HashMap::new())),
         }
     }
 
+    pub fn population(&self) -> &str {
+        &self.population
+    }
+
+    fn own_cars<'a>(&self, cars: Vec<&'a car::Car>) -> Vec<&'a car::Car> {
+        cars.into_iter().filter(|car| car.class == self.population).collect()
+    }
+
     pub fn register(&mut self, id: Uuid) {
         self.controllers.borrow_mut().insert(id, vec![]);
     }
 
-    pub async fn new_network(&mut self) {
+    // starts training the class from scratch
+    pub async fn new_network(&mut self, num_of_cars: u32) -> Result<(), GameError> {
+        self.request_network(num_of_cars, false).await
+    }
+
+    // races with what the class has learned so far, a class the server does not know yet starts from scratch
+    pub async fn load_network(&mut self, num_of_cars: u32) -> Result<(), GameError> {
+        self.request_network(num_of_cars, true).await
+    }
+
+    async fn request_network(&mut self, num_of_cars: u32, keep: bool) -> Result<(), GameError> {
         let request = NewGenRequest{
-            num_of_cars: self.controllers.borrow().len() as u32,
-            num_of_args: 7,
-            num_of_outputs: 4,
+            population: self.population.clone(),
+            num_of_cars,
+            num_of_args: BASE_INPUTS + self.damage_input as u32 + 2 * self.strategy_inputs as u32,
+            num_of_outputs: NUM_OUTPUTS,
+            keep,
+            seed: self.seed,
         };
         let request = serde_json::to_string(&request).unwrap();
-        let request = Some(request.as_str());
-        let result = post("http://127.0.0.1:3030/api/gen_network", request).await;
-        if let Err(value) = result {
-            crate::log(value.as_string().unwrap().as_str());
-        }
+        post(&self.api_url("/api/gen_network"), Some(request.as_str())).await?;
+        Ok(())
     }
 
     pub fn evaluate(&mut self, cars: Vec<&car::Car>) {
@@ -112,24 +166,39 @@ impl AutoDrive {
             return
         }
         self.timer.reset();
+        let cars = self.own_cars(cars);
         let controllers = self.controllers.clone();
+        let url = self.api_url("/api/evaluate_network");
         let ids: Vec<Uuid> = cars.iter().map(| car | car.id).collect();
+        let (damage_input, strategy_inputs) = (self.damage_input, self.strategy_inputs);
         let request = EvaluteRequest{
+            population: self.population.clone(),
             inputs: cars.iter().map(|car| { 
-                let forward = car.sensor.forward_dis / car_sensor::SENSOR_RANGE;
-                let right = car.sensor.right_dis / car_sensor::SENSOR_RANGE;
-                let back = car.sensor.back_dis / car_sensor::SENSOR_RANGE;
-                let left = car.sensor.left_dis / car_sensor::SENSOR_RANGE;
-                let velocity = car.velocity / car_sensor::SENSOR_RANGE;
+                let forward = car.sensor.forward_dis / car.sensor.range;
+                let right = car.sensor.right_dis / car.sensor.range;
+                let back = car.sensor.back_dis / car.sensor.range;
+                let left = car.sensor.left_dis / car.sensor.range;
+                let velocity = car.velocity() / car.sensor.range;
                 let rotate = car.body.rotate / PI;
                 let track_dir = car.sensor.track_direction / PI;
-                vec![forward, right, back, left, velocity, rotate, track_dir] 
+                let mut inputs = vec![forward, right, back, left, velocity, rotate, track_dir];
+                if damage_input {
+                    inputs.push(car.damage.amount);
+                }
+                if strategy_inputs {
+                    inputs.push(car.consumables.fuel_level());
+                    inputs.push(car.consumables.tire_wear);
+                }
+                inputs
             }).collect(),
         };
         spawn_local(async move {
             let request = serde_json::to_string(&request).unwrap();
             let request = Some(request.as_str());
-            let result = post("http://127.0.0.1:3030/api/evaluate_network", request).await;
+            let result = post(&url, request).await;
+            if let Err(ref value) = result {
+                crate::log_warn!("evaluating {:?}", value);
+            }
             if let Ok(ref result) = result {
                 if let Some(result) = result.as_string() {
                     if let Ok(outputs) = serde_json::from_str::<Vec<Vec<f64>>>(&result) {
@@ -144,35 +213,44 @@ impl AutoDrive {
     }
 
     pub fn next_gen(&mut self, cars: Vec<&car::Car>) {
+        let cars = self.own_cars(cars);
         let scores: Vec<f64> = cars.iter().map(| car | {
             car.score.score
         }).collect();
-        crate::console_log!("max fitness is {:?}", scores.iter().max_by(| x, y | { 
+        crate::log_info!("{} max fitness is {:?}", self.population, scores.iter().max_by(| x, y | { 
             if x > y {
                 return std::cmp::Ordering::Greater;
             }
             return std::cmp::Ordering::Less;
         }));
+        let (fitness_url, next_gen_url) = (self.api_url("/api/set_fitness"), self.api_url("/api/next_gen"));
+        let population = self.population.clone();
         spawn_local(async move {
             let request = SetFitnessesRequest{
+                population: population.clone(),
                 fitnesses: scores
             };
             let request = serde_json::to_string(&request).unwrap();
             let request = Some(request.as_str());
-            let result = post("http://127.0.0.1:3030/api/set_fitness", request).await;
+            let result = post(&fitness_url, request).await;
             if let Err(value) = result {
-                crate::log(value.as_string().unwrap().as_str());
+                crate::log_error!("{:?}", value);
                 return
             }
 
-            let result = post("http://127.0.0.1:3030/api/next_gen", None).await;
+            let request = serde_json::to_string(&NextGenRequest { population }).unwrap();
+            let result = post(&next_gen_url, Some(request.as_str())).await;
             if let Err(value) = result {
-                crate::log(value.as_string().unwrap().as_str());
+                crate::log_error!("{:?}", value);
                 return
             }
         });
     }
 
+    fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.server_url.trim_end_matches('/'), path)
+    }
+
     pub fn tick(&mut self, delta: f64) {
         self.timer.tick(delta)
     }
@@ -200,36 +278,60 @@ impl CarController for AutoDriveController {
         return self.id
     }
 
-    fn next_movements(&self, _car: &Car) -> Vec<Movement> {
-        let mut movements = vec![];
-        if let Some(outputs) = self.auto_drive.borrow().controllers.borrow().get(&self.id) {
-            for (i, output) in outputs.iter().enumerate() {
-                match i {
-                    0 => {
-                        if *output > 0.5 {
-                            movements.push(Movement::Forward);
-                        }
-                    },
-                    1 => {
-                        if *output > 0.5 {
-                            movements.push(Movement::Right);
-                        }
-                    },
-                    2 => {
-                        if *output > 0.5 {
-                            movements.push(Movement::Backward);
-                        }
-                    },
-                    3 => {
-                        if *output > 0.5 {
-                            movements.push(Movement::Left);
-                        }
-                    },
-                    _ => {}
-                }
+    fn next_input(&self, _car: &Car) -> ControlInput {
+        let auto_drive = self.auto_drive.borrow();
+        let controllers = auto_drive.controllers.borrow();
+        match controllers.get(&self.id) {
+            Some(outputs) if auto_drive.discrete_outputs => ControlInput::from_movements(&movements_from_outputs(outputs)),
+            Some(outputs) => input_from_outputs(outputs),
+            None => ControlInput::default(),
+        }
+    }
+}
+
+// network outputs are forward, right, backward, left and handbrake in the 0 to 1 range
+pub fn input_from_outputs(outputs: &[f64]) -> ControlInput {
+    let output = |i: usize| outputs.get(i).cloned().unwrap_or(0.0);
+    ControlInput {
+        throttle: output(0),
+        brake: output(2),
+        steer: output(1) - output(3),
+        handbrake: output(4),
+    }
+}
+
+// the original thresholded mapping, kept for networks trained with it
+pub fn movements_from_outputs(outputs: &[f64]) -> Vec<Movement> {
+    let mut movements = vec![];
+    for (i, output) in outputs.iter().enumerate() {
+        if *output <= 0.5 {
+            continue;
+        }
+        match i {
+            0 => movements.push(Movement::Forward),
+            1 => movements.push(Movement::Right),
+            2 => movements.push(Movement::Backward),
+            3 => movements.push(Movement::Left),
+            _ => {}
+        }
+    }
+    movements
+}
+
+impl ControlInput {
+    pub fn from_movements(movements: &[Movement]) -> Self {
+        let mut input = ControlInput::default();
+        for movement in movements {
+            match movement {
+                Movement::Left => input.steer = -1.0,
+                Movement::Right => input.steer = 1.0,
+                Movement::NotTurning => input.steer = 0.0,
+                Movement::Forward => input.throttle = 1.0,
+                Movement::Backward => input.brake = 1.0,
+                Movement::NotAccelarate => {},
             }
         }
-        return movements;
+        input
     }
 }
 
@@ -238,31 +340,25 @@ impl CarController for SimpleController {
         Uuid::new_v4()
     }
 
-    fn next_movements(&self, car: &Car) -> Vec<Movement> {
-        let mut movements = vec![];
-        movements.push(Movement::Forward);
-        return movements;
+    fn next_input(&self, _car: &Car) -> ControlInput {
+        ControlInput { throttle: 1.0, ..ControlInput::default() }
     }
 }
 
-impl CarController for KeyController {
-    fn get_id(&self) -> Uuid {
-        self.id
-    }
-
-    fn next_movements(&self, car: &Car) -> Vec<Movement> {
+impl KeyController {
+    fn next_movements(&self) -> Vec<Movement> {
         let keyboard_state = self.keyboard_state.clone();
         let mut movements = vec![];
-        if keyboard_state.borrow().is_pressed("ArrowLeft") {
+        if keyboard_state.borrow().is_pressed(&self.key_bindings.left) {
             movements.push(Movement::Left);
-        } else if keyboard_state.borrow().is_pressed("ArrowRight") {
+        } else if keyboard_state.borrow().is_pressed(&self.key_bindings.right) {
             movements.push(Movement::Right);
         } else {
             movements.push(Movement::NotTurning);
         }
-        if keyboard_state.borrow().is_pressed("ArrowUp") {
+        if keyboard_state.borrow().is_pressed(&self.key_bindings.accelerate) {
             movements.push(Movement::Forward);
-        } else if keyboard_state.borrow().is_pressed("ArrowDown") {
+        } else if keyboard_state.borrow().is_pressed(&self.key_bindings.brake) {
             movements.push(Movement::Backward);
         } else {
             movements.push(Movement::NotAccelarate);
@@ -270,3 +366,45 @@ impl CarController for KeyController {
         return movements;
     }
 }
+
+impl CarController for KeyController {
+    fn get_id(&self) -> Uuid {
+        self.id
+    }
+
+    fn next_input(&self, _car: &Car) -> ControlInput {
+        let mut input = ControlInput::from_movements(&self.next_movements());
+        if self.keyboard_state.borrow().is_pressed(&self.key_bindings.handbrake) {
+            input.handbrake = 1.0;
+        }
+        input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_movements() {
+        let input = ControlInput::from_movements(&[Movement::Left, Movement::Forward]);
+        assert_eq!(ControlInput { throttle: 1.0, steer: -1.0, ..ControlInput::default() }, input);
+    }
+
+    #[test]
+    fn test_analog_outputs() {
+        let input = input_from_outputs(&[0.8, 0.7, 0.1, 0.2]);
+        assert_eq!(0.8, input.throttle);
+        assert_eq!(0.1, input.brake);
+        assert!((input.steer - 0.5).abs() < 1e-9, "steer should be right minus left");
+        assert_eq!(0.0, input.handbrake, "missing outputs should be zero");
+        let input = input_from_outputs(&[0.8, 0.7, 0.1, 0.2, 0.9]);
+        assert_eq!(0.9, input.handbrake);
+    }
+
+    #[test]
+    fn test_discrete_outputs() {
+        let movements = movements_from_outputs(&[0.8, 0.7, 0.1, 0.2]);
+        assert_eq!(ControlInput { throttle: 1.0, steer: 1.0, ..ControlInput::default() }, ControlInput::from_movements(&movements));
+    }
+}
@@ -5,14 +5,13 @@ use crate::game::track::*;
 use crate::engine::*;
 use ndarray::arr2;
 
-pub const SENSOR_RANGE: f64 = 200.0;
-
 #[derive(Debug)]
 pub struct Sensor {
     forward: Line,
     right: Line,
     back: Line,
     left: Line,
+    pub range: f64,
     pub forward_dis: f64,
     pub right_dis: f64,
     pub back_dis: f64,
@@ -21,23 +20,25 @@ pub struct Sensor {
 }
 
 impl Sensor {
-    pub fn new() -> Self {
+    pub fn new(range: f64) -> Self {
         Sensor { 
+            range,
             forward: Line::default(),
             right: Line::default(),
             back: Line::default(),
             left: Line::default(),
-            forward_dis: SENSOR_RANGE,
-            right_dis: SENSOR_RANGE,
-            back_dis: SENSOR_RANGE,
-            left_dis: SENSOR_RANGE,
+            forward_dis: range,
+            right_dis: range,
+            back_dis: range,
+            left_dis: range,
             track_direction: 0.0
         }
     }
     
     pub fn reset(&mut self, center_point: FVec, rotate: f64) {
         let (cx, cy) = (center_point.x, center_point.y);
-        let ps = arr2(&[[0.0, SENSOR_RANGE, 0.0, -SENSOR_RANGE], [SENSOR_RANGE, 0.0, -SENSOR_RANGE, 0.0]]);
+        let range = self.range;
+        let ps = arr2(&[[0.0, range, 0.0, -range], [range, 0.0, -range, 0.0]]);
         let r = arr2(&[[rotate.cos(), -rotate.sin()], [rotate.sin(), rotate.cos()]]);
         let c = arr2(&[[ cx, cx, cx, cx ], [ cy, cy, cy, cy ]]);
         let pc = &r.dot(&ps) + &c;
@@ -55,22 +56,22 @@ impl Sensor {
         self.back.end = coordinates[2];
         self.left.end = coordinates[3];
 
-        self.forward_dis = SENSOR_RANGE;
-        self.right_dis = SENSOR_RANGE;
-        self.back_dis = SENSOR_RANGE;
-        self.left_dis = SENSOR_RANGE;
+        self.forward_dis = range;
+        self.right_dis = range;
+        self.back_dis = range;
+        self.left_dis = range;
     }
 
     pub fn debug(&self, renderer: &Renderer) {
+        renderer.save();
         renderer.stroke_style("blue");
-        renderer.line(&self.forward);
-        renderer.line(&self.right);
-        renderer.line(&self.back);
-        renderer.line(&self.left);
-        renderer.text(format!("{}", self.forward_dis).as_str(), self.forward.end);
-        renderer.text(format!("{}", self.right_dis).as_str(), self.right.end);
-        renderer.text(format!("{}", self.back_dis).as_str(), self.back.end);
-        renderer.text(format!("{}", self.left_dis).as_str(), self.left.end);
+        renderer.fill_style("blue");
+        renderer.font("10px monospace");
+        for (ray, distance) in [(&self.forward, self.forward_dis), (&self.right, self.right_dis), (&self.back, self.back_dis), (&self.left, self.left_dis)] {
+            renderer.line(ray);
+            renderer.fill_text(format!("{:.0}", distance).as_str(), ray.end);
+        }
+        renderer.restore();
     }
 }
 
@@ -83,10 +84,9 @@ impl Car {
     }
 
     pub fn detect(&mut self, track: &Track) {
-        use crate::game::track::TrackSegmentDirection::*;
         for seg in track.segments.iter() {
             if let (Some(car_center), Some(track_center)) = (self.body.get_center(), seg.body.get_center()) {
-                if car_center.distance(&track_center) > SENSOR_RANGE * 2.0 {
+                if car_center.distance(&track_center) > self.sensor.range * 2.0 {
                     continue;
                 }
                 for boundary in &seg.boundaries {
@@ -112,20 +112,8 @@ impl Car {
                     }
                 };
                 if let Some(seg) = track.on_which_track_seg(&self.body) {
-                    self.sensor.track_direction = match seg.direction {
-                        Up => 2.0 * PI * 6.0 / 8.0,
-                        UpRightRight => 2.0 * PI * 7.0 / 8.0,
-                        UpRightUp => 2.0 * PI * 7.0 / 8.0,
-                        Right => 0.0,
-                        DownRightRight => 2.0 * PI * 1.0 / 8.0,
-                        DownRightDown => 2.0 * PI * 1.0 / 8.0,
-                        Down => 2.0 * PI * 2.0 / 8.0,
-                        DownLeftLeft => 2.0 * PI * 3.0 / 8.0,
-                        DownLeftDown => 2.0 * PI * 3.0 / 8.0,
-                        Left => 2.0 * PI * 4.0 / 8.0,
-                        UpLeftLeft => 2.0 * PI * 5.0 / 8.0,
-                        UpLeftUp => 2.0 * PI * 5.0 / 8.0,
-                    };
+                    let heading = seg.heading.y.atan2(seg.heading.x);
+                    self.sensor.track_direction = if heading < 0.0 { heading + 2.0 * PI } else { heading };
                 }
             }
         }
@@ -0,0 +1,126 @@
+use crate::config::CarConfig;
+
+// a car slower than this on the pit lane is stopping for service
+const PIT_ENTRY_SPEED: f64 = 30.0;
+// worn out tires keep this share of their grip
+const WORN_GRIP: f64 = 0.6;
+
+#[derive(Debug, Clone)]
+pub struct Consumables {
+    // fuel is in the same units as the car mass, tire wear goes from 0 for new tires to 1
+    pub fuel: f64,
+    pub tire_wear: f64,
+    // time left on the current pit stop
+    pub pit_stop: Option<f64>,
+    capacity: f64,
+    consumption: f64,
+    wear_rate: f64,
+    // a car is serviced once per visit to the pit lane
+    serviced: bool,
+}
+
+impl Consumables {
+    pub fn new(spec: &CarConfig) -> Self {
+        Consumables {
+            fuel: spec.fuel_capacity,
+            tire_wear: 0.0,
+            pit_stop: None,
+            capacity: spec.fuel_capacity,
+            consumption: spec.fuel_consumption,
+            wear_rate: spec.tire_wear_rate,
+            serviced: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.fuel = self.capacity;
+        self.tire_wear = 0.0;
+        self.pit_stop = None;
+        self.serviced = false;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fuel <= 0.0
+    }
+
+    pub fn fuel_level(&self) -> f64 {
+        if self.capacity > 0.0 { self.fuel / self.capacity } else { 0.0 }
+    }
+
+    pub fn grip_factor(&self) -> f64 {
+        1.0 - (1.0 - WORN_GRIP) * self.tire_wear
+    }
+
+    // fuel burns with the throttle, tires wear with the distance whatever the driver does
+    pub fn drive(&mut self, distance: f64, throttle: f64) {
+        self.fuel = (self.fuel - distance * throttle * self.consumption).max(0.0);
+        self.tire_wear = (self.tire_wear + distance * self.wear_rate).min(1.0);
+    }
+
+    // returns true on the tick a stop starts so the car can be brought to rest
+    pub fn update_pit(&mut self, on_pit_lane: bool, speed: f64, stop_time: f64, delta: f64) -> bool {
+        if let Some(remaining) = self.pit_stop {
+            let remaining = remaining - delta;
+            if remaining > 0.0 {
+                self.pit_stop = Some(remaining);
+            } else {
+                self.pit_stop = None;
+                self.fuel = self.capacity;
+                self.tire_wear = 0.0;
+            }
+            return false;
+        }
+        if !on_pit_lane {
+            self.serviced = false;
+            return false;
+        }
+        if !self.serviced && speed < PIT_ENTRY_SPEED {
+            self.serviced = true;
+            self.pit_stop = Some(stop_time);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consumables() -> Consumables {
+        Consumables::new(&CarConfig { fuel_capacity: 1.0, fuel_consumption: 0.001, tire_wear_rate: 0.0005, ..CarConfig::default() })
+    }
+
+    #[test]
+    fn test_drive_burns_fuel_and_wears_tires() {
+        let mut consumables = consumables();
+        consumables.drive(500.0, 1.0);
+        assert!((consumables.fuel - 0.5).abs() < 1e-9);
+        assert!((consumables.tire_wear - 0.25).abs() < 1e-9);
+        consumables.drive(500.0, 0.0);
+        assert!((consumables.fuel - 0.5).abs() < 1e-9, "coasting should not burn fuel");
+        consumables.drive(5000.0, 1.0);
+        assert!(consumables.is_empty());
+        assert_eq!(1.0, consumables.tire_wear, "wear should be capped");
+        assert!((consumables.grip_factor() - WORN_GRIP).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pit_stop() {
+        let mut consumables = consumables();
+        consumables.drive(800.0, 1.0);
+        assert!(!consumables.update_pit(true, 200.0, 3.0, 0.1), "driving through should not stop");
+        assert!(consumables.update_pit(true, 10.0, 3.0, 0.1), "slowing down on the pit lane should stop");
+        for _ in 0..29 {
+            consumables.update_pit(true, 0.0, 3.0, 0.1);
+        }
+        assert!(consumables.pit_stop.is_some(), "the stop should take its time");
+        consumables.update_pit(true, 0.0, 3.0, 0.2);
+        assert!(consumables.pit_stop.is_none());
+        assert_eq!(1.0, consumables.fuel);
+        assert_eq!(0.0, consumables.tire_wear);
+        assert!(!consumables.update_pit(true, 0.0, 3.0, 0.1), "one stop per visit");
+        consumables.update_pit(false, 100.0, 3.0, 0.1);
+        assert!(consumables.update_pit(true, 0.0, 3.0, 0.1), "the next visit can stop again");
+    }
+}
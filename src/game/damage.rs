@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub const RETIRE_AT: f64 = 1.0;
+// slower impacts along the contact normal are scrapes and do no harm
+const IMPACT_THRESHOLD: f64 = 80.0;
+// a head on hit at 400px/s retires a fresh car
+const DAMAGE_PER_SPEED: f64 = 1.0 / 320.0;
+
+#[derive(Debug, Default, Clone)]
+pub struct Damage {
+    pub amount: f64,
+    // a shielded car takes no damage but still tracks its contacts
+    pub shielded: bool,
+    touching_wall: bool,
+    touching_cars: HashSet<Uuid>,
+}
+
+impl Damage {
+    pub fn new() -> Self {
+        Damage::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Damage::default();
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.amount >= RETIRE_AT
+    }
+
+    // `speed` is the closing speed along the contact normal
+    pub fn impact(&mut self, speed: f64) -> f64 {
+        if self.shielded {
+            return 0.0;
+        }
+        let damage = (speed.abs() - IMPACT_THRESHOLD).max(0.0) * DAMAGE_PER_SPEED;
+        self.amount = (self.amount + damage).min(RETIRE_AT);
+        damage
+    }
+
+    // a contact lasts several ticks, only the tick it starts on is an impact
+    pub fn wall_contact(&mut self, speed: Option<f64>) {
+        if let (Some(speed), false) = (speed, self.touching_wall) {
+            self.impact(speed);
+        }
+        self.touching_wall = speed.is_some();
+    }
+
+    pub fn car_contacts(&mut self, contacts: &[(Uuid, f64)]) {
+        for (id, speed) in contacts {
+            if !self.touching_cars.contains(id) {
+                self.impact(*speed);
+            }
+        }
+        self.touching_cars = contacts.iter().map(|(id, _)| *id).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impact_scales_with_speed() {
+        let mut damage = Damage::new();
+        assert_eq!(0.0, damage.impact(IMPACT_THRESHOLD), "scrapes should be free");
+        let light = damage.impact(150.0);
+        let heavy = damage.impact(-300.0);
+        assert!(light > 0.0 && heavy > light, "faster impacts should do more damage");
+        assert!(!damage.is_retired());
+        damage.impact(1000.0);
+        assert!(damage.is_retired());
+        assert_eq!(RETIRE_AT, damage.amount, "damage should be capped");
+    }
+
+    #[test]
+    fn test_contact_counts_once() {
+        let mut damage = Damage::new();
+        for _ in 0..5 {
+            damage.wall_contact(Some(200.0));
+        }
+        let once = damage.amount;
+        assert!(once > 0.0);
+        damage.wall_contact(None);
+        damage.wall_contact(Some(200.0));
+        assert!((damage.amount - once * 2.0).abs() < 1e-9, "a new contact should be a new impact");
+    }
+
+    #[test]
+    fn test_car_contacts() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut damage = Damage::new();
+        damage.car_contacts(&[(a, 200.0)]);
+        let after_a = damage.amount;
+        damage.car_contacts(&[(a, 200.0), (b, 200.0)]);
+        assert!((damage.amount - after_a * 2.0).abs() < 1e-9, "only the new car should count");
+    }
+
+    #[test]
+    fn test_shield() {
+        let mut damage = Damage::new();
+        damage.shielded = true;
+        damage.wall_contact(Some(400.0));
+        assert_eq!(0.0, damage.amount);
+    }
+}
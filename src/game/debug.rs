@@ -0,0 +1,144 @@
+use super::*;
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugFlags {
+    pub enabled: bool,
+    pub sensors: bool,
+    pub boundaries: bool,
+    pub hitboxes: bool,
+    pub stats: bool,
+    pub profiler: bool,
+}
+
+impl DebugFlags {
+    // keys `claimed` by the current stage do not toggle anything
+    pub fn handle_input(&mut self, keyboard_state: &KeyboardState, claimed: impl Fn(&str) -> bool) {
+        self.toggle(|code| keyboard_state.was_pressed(code) && !claimed(code));
+    }
+
+    fn toggle(&mut self, was_pressed: impl Fn(&str) -> bool) {
+        if was_pressed("Backquote") {
+            self.enabled = !self.enabled;
+            if self.enabled && !(self.sensors || self.boundaries || self.hitboxes || self.stats) {
+                self.sensors = true;
+                self.boundaries = true;
+                self.hitboxes = true;
+                self.stats = true;
+            }
+        }
+        // a hidden overlay stops the profiler too, nothing would show what it measures
+        profiler::set_enabled(self.enabled && self.profiler);
+        if !self.enabled {
+            return;
+        }
+        if was_pressed("Digit1") {
+            self.sensors = !self.sensors;
+        }
+        if was_pressed("Digit2") {
+            self.boundaries = !self.boundaries;
+        }
+        if was_pressed("Digit3") {
+            self.hitboxes = !self.hitboxes;
+        }
+        if was_pressed("Digit4") {
+            self.stats = !self.stats;
+        }
+        if was_pressed("Digit5") {
+            self.profiler = !self.profiler;
+        }
+        if was_pressed("Digit6") {
+            crate::log_info!("{}", profiler::report_json());
+        }
+        profiler::set_enabled(self.enabled && self.profiler);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    window_start: f64,
+    frames: u32,
+    ticks: u32,
+    pub fps: f64,
+    pub tps: f64,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        FrameStats::default()
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    // timestamps are in milliseconds, rates are refreshed once per second
+    pub fn frame(&mut self, now: f64) {
+        self.frames += 1;
+        let ellapsed = now - self.window_start;
+        if ellapsed >= 1000.0 {
+            self.fps = self.frames as f64 * 1000.0 / ellapsed;
+            self.tps = self.ticks as f64 * 1000.0 / ellapsed;
+            self.frames = 0;
+            self.ticks = 0;
+            self.window_start = now;
+        }
+    }
+
+    pub fn draw(&self, renderer: &Renderer, width: f64) {
+        renderer.save();
+        renderer.font("14px monospace");
+        renderer.text_align(TextAlign::Right);
+        renderer.text_baseline(TextBaseline::Top);
+        renderer.fill_style("lime");
+        renderer.fill_text(&format!("FPS {:.0}  TPS {:.0}", self.fps, self.tps), FVec::new(width - 10.0, 10.0));
+        renderer.restore();
+    }
+}
+
+pub fn draw_profile(renderer: &Renderer, width: f64) {
+    let report = profiler::report();
+    let total: f64 = report.iter().map(|phase| phase.average_ms).sum();
+    renderer.save();
+    renderer.font("12px monospace");
+    renderer.text_align(TextAlign::Right);
+    renderer.text_baseline(TextBaseline::Top);
+    renderer.fill_style("rgba(0, 0, 0, 0.6)");
+    renderer.rounded_rect(&Rect { x: width - 260.0, y: 30.0, w: 250.0, h: 16.0 * (report.len() + 1) as f64 + 12.0 }, 6.0, true);
+    renderer.fill_style("lime");
+    for (i, phase) in report.iter().enumerate() {
+        let line = format!("{:?} {:6.2}ms (max {:6.2})", phase.phase, phase.average_ms, phase.max_ms);
+        renderer.fill_text(&line, FVec::new(width - 20.0, 36.0 + 16.0 * i as f64));
+    }
+    renderer.fill_text(&format!("Total {:6.2}ms", total), FVec::new(width - 20.0, 36.0 + 16.0 * report.len() as f64));
+    renderer.restore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_stats() {
+        let mut stats = FrameStats::new();
+        for i in 1..=60 {
+            stats.tick();
+            stats.tick();
+            stats.frame(i as f64 * 1000.0 / 60.0);
+        }
+        assert!((stats.fps - 60.0).abs() < 1e-9, "should be 60 fps");
+        assert!((stats.tps - 120.0).abs() < 1e-9, "should be 120 ticks per second");
+    }
+
+    #[test]
+    fn test_hiding_overlay_stops_profiler() {
+        let mut flags = DebugFlags { enabled: true, profiler: true, ..DebugFlags::default() };
+        flags.toggle(|_| false);
+        assert!(profiler::is_enabled());
+        flags.toggle(|code| code == "Backquote");
+        assert!(!flags.enabled);
+        assert!(flags.profiler, "the profiler is back when the overlay is shown again");
+        assert!(!profiler::is_enabled(), "a hidden overlay should not keep profiling");
+    }
+}
@@ -0,0 +1,59 @@
+use web_sys::HtmlCanvasElement;
+use super::*;
+use crate::error::GameError;
+
+const PANEL_WIDTH: f64 = 600.0;
+const PANEL_HEIGHT: f64 = 160.0;
+
+pub fn draw_error_panel(renderer: &Renderer, center: FVec, error: &GameError) {
+    renderer.save();
+    renderer.fill_style("rgba(120, 0, 0, 0.9)");
+    renderer.rounded_rect(&Rect { x: center.x - PANEL_WIDTH / 2.0, y: center.y - PANEL_HEIGHT / 2.0, w: PANEL_WIDTH, h: PANEL_HEIGHT }, 10.0, true);
+    renderer.fill_style("white");
+    renderer.text_align(TextAlign::Center);
+    renderer.text_baseline(TextBaseline::Middle);
+    renderer.font("bold 28px sans-serif");
+    renderer.fill_text("Something went wrong", FVec::new(center.x, center.y - 36.0));
+    renderer.font("18px sans-serif");
+    renderer.fill_text(&error.to_string(), FVec::new(center.x, center.y + 8.0));
+    renderer.font("14px sans-serif");
+    renderer.fill_text("see the browser console for details", FVec::new(center.x, center.y + 44.0));
+    renderer.restore();
+}
+
+// used when the game could not even start, so there is no render loop to draw with
+pub fn show_error(canvas: &HtmlCanvasElement, error: &GameError) {
+    if let Ok(context) = get_context(canvas) {
+        let renderer = Renderer { context };
+        let (width, height) = (canvas.width() as f64, canvas.height() as f64);
+        renderer.clear(&Rect { x: 0.0, y: 0.0, w: width, h: height });
+        draw_error_panel(&renderer, FVec::new(width / 2.0, height / 2.0), error);
+    }
+}
+
+pub struct ErrorStage {
+    error: GameError,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    // the size of the canvas
+    size: FVec,
+}
+
+impl ErrorStage {
+    pub fn new(keyboard_state: Rc<RefCell<KeyboardState>>, error: GameError, size: FVec) -> Box<Self> {
+        Box::new(ErrorStage { error, keyboard_state, size })
+    }
+}
+
+impl Stage for ErrorStage {
+    fn update(&mut self, _delta: f64) -> Transition {
+        let keyboard_state = self.keyboard_state.borrow();
+        if keyboard_state.was_pressed("Enter") || keyboard_state.was_pressed("Escape") {
+            return Transition::Pop;
+        }
+        Transition::None
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        draw_error_panel(renderer, FVec::new(self.size.x / 2.0, self.size.y / 2.0), &self.error);
+    }
+}
@@ -0,0 +1,120 @@
+use super::*;
+
+const HUD_X: f64 = 20.0;
+const HUD_Y: f64 = 20.0;
+const HUD_LINE_HEIGHT: f64 = 20.0;
+
+#[derive(Debug, Clone)]
+pub enum HudInfo {
+    Race {
+        speed: f64,
+        lap: u32,
+        // laps in the race, 0 when it has no end
+        laps: u32,
+        lap_time: f64,
+        lap_times: Vec<f64>,
+        // the player's best lap ever on the track
+        record: Option<f64>,
+        damage: f64,
+        fuel: f64,
+        tire_wear: f64,
+        pit_stop: Option<f64>,
+        effects: Vec<(String, f64)>,
+    },
+    Training {
+        generation: i64,
+        alive: usize,
+        total: usize,
+        best_score: f64,
+        average_score: f64,
+        generation_time: f64,
+    },
+}
+
+pub fn best_and_average(scores: &[f64]) -> (f64, f64) {
+    if scores.is_empty() {
+        return (0.0, 0.0);
+    }
+    let best = scores.iter().cloned().fold(f64::MIN, f64::max);
+    let average = scores.iter().sum::<f64>() / scores.len() as f64;
+    (best, average)
+}
+
+pub fn format_time(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:06.3}", minutes, seconds - minutes * 60.0)
+}
+
+impl HudInfo {
+    fn lines(&self) -> Vec<String> {
+        match self {
+            HudInfo::Race { speed, lap, laps, lap_time, lap_times, record, damage, fuel, tire_wear, pit_stop, effects } => {
+                let mut lines = vec![
+                    format!("Speed: {:.0}", speed.abs()),
+                    format!("Damage: {:.0}%", damage * 100.0),
+                    format!("Fuel: {:.0}%", fuel * 100.0),
+                    format!("Tire wear: {:.0}%", tire_wear * 100.0),
+                    if *laps > 0 { format!("Lap: {}/{}", lap, laps) } else { format!("Lap: {}", lap) },
+                    format!("Lap time: {}", format_time(*lap_time)),
+                ];
+                if let Some(record) = record {
+                    lines.push(format!("Record: {}", format_time(*record)));
+                }
+                for (i, time) in lap_times.iter().enumerate() {
+                    lines.push(format!("Lap {}: {}", i + 1, format_time(*time)));
+                }
+                if let Some(remaining) = pit_stop {
+                    lines.push(format!("Pit stop: {:.1}s", remaining));
+                }
+                for (name, remaining) in effects {
+                    lines.push(format!("{}: {:.1}s", name, remaining));
+                }
+                lines
+            },
+            HudInfo::Training { generation, alive, total, best_score, average_score, generation_time } => vec![
+                format!("Generation: {}", generation),
+                format!("Alive: {}/{}", alive, total),
+                format!("Best score: {:.0}", best_score),
+                format!("Average score: {:.0}", average_score),
+                format!("Generation time: {}", format_time(*generation_time)),
+            ],
+        }
+    }
+
+    pub fn draw(&self, renderer: &Renderer) {
+        let lines = self.lines();
+        renderer.save();
+        renderer.font("14px monospace");
+        renderer.text_baseline(TextBaseline::Top);
+        let width = lines.iter().map(|line| renderer.measure_text(line)).fold(0.0, f64::max);
+        renderer.fill_style("rgba(0, 0, 0, 0.6)");
+        renderer.rounded_rect(&Rect { x: HUD_X - 10.0, y: HUD_Y - 10.0, w: width + 20.0, h: HUD_LINE_HEIGHT * lines.len() as f64 + 16.0 }, 6.0, true);
+        renderer.fill_style("white");
+        for (i, line) in lines.iter().enumerate() {
+            renderer.fill_text(line, FVec::new(HUD_X, HUD_Y + HUD_LINE_HEIGHT * i as f64));
+        }
+        renderer.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_and_average() {
+        let (best, average) = best_and_average(&[1.0, 5.0, 3.0]);
+        assert_eq!(5.0, best, "should be the max score");
+        assert_eq!(3.0, average, "should be the mean score");
+    }
+
+    #[test]
+    fn test_best_and_average_empty() {
+        assert_eq!((0.0, 0.0), best_and_average(&[]), "should be zero");
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!("1:05.250", format_time(65.25));
+    }
+}
@@ -0,0 +1,102 @@
+use super::*;
+use super::track::Gate;
+
+// times the laps of one car, a lap only counts once every gate of the track is crossed in order
+#[derive(Debug, Default)]
+pub struct LapTimer {
+    // laps completed so far
+    pub laps: u32,
+    pub lap_time: f64,
+    pub lap_times: Vec<f64>,
+    pub best_lap: Option<f64>,
+    next_gate: usize,
+    position: Option<FVec>,
+}
+
+impl LapTimer {
+    pub fn new() -> Self {
+        LapTimer::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = LapTimer::default();
+    }
+
+    // the lap being driven, counted from one
+    pub fn lap(&self) -> u32 {
+        self.laps + 1
+    }
+
+    pub fn last_lap(&self) -> Option<f64> {
+        self.lap_times.last().cloned()
+    }
+
+    // returns the time of the lap finished on this tick
+    pub fn update(&mut self, position: FVec, gates: &[Gate], delta: f64) -> Option<f64> {
+        self.lap_time += delta;
+        let from = self.position.replace(position)?;
+        let gate = gates.get(self.next_gate)?;
+        if !gate.crossed_by(&from, &position) {
+            return None;
+        }
+        self.next_gate += 1;
+        if self.next_gate < gates.len() {
+            return None;
+        }
+        let time = self.lap_time;
+        self.next_gate = 0;
+        self.lap_time = 0.0;
+        self.laps += 1;
+        self.lap_times.push(time);
+        self.best_lap = Some(self.best_lap.map_or(time, |best| best.min(time)));
+        Some(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a checkpoint across x = 100 and the finish line across x = 0, both driven over to the right
+    fn gates() -> Vec<Gate> {
+        let heading = FVec::new(1.0, 0.0);
+        vec![
+            Gate { line: Line::new(100.0, -50.0, 100.0, 50.0), heading },
+            Gate { line: Line::new(0.0, -50.0, 0.0, 50.0), heading },
+        ]
+    }
+
+    fn drive(timer: &mut LapTimer, xs: &[f64]) -> Vec<f64> {
+        xs.iter().filter_map(|x| timer.update(FVec::new(*x, 0.0), &gates(), 1.0)).collect()
+    }
+
+    #[test]
+    fn test_lap() {
+        let mut timer = LapTimer::new();
+        // the first lap is timed from the start, crossing the line before the checkpoint does not finish it
+        assert_eq!(vec![5.0], drive(&mut timer, &[-10.0, 50.0, 110.0, -10.0, 10.0]));
+        assert_eq!(2, timer.lap());
+        assert_eq!(0.0, timer.lap_time);
+        assert_eq!(vec![3.0], drive(&mut timer, &[110.0, -10.0, 10.0]));
+        assert_eq!(vec![5.0, 3.0], timer.lap_times);
+    }
+
+    #[test]
+    fn test_checkpoints_in_order() {
+        let mut timer = LapTimer::new();
+        assert!(drive(&mut timer, &[-10.0, 10.0, -10.0, 10.0]).is_empty(), "the finish line does not count before the checkpoint");
+        let mut timer = LapTimer::new();
+        assert!(drive(&mut timer, &[110.0, 90.0, -10.0, 10.0]).is_empty(), "crossing the checkpoint backwards does not count");
+        assert_eq!(0, timer.laps);
+    }
+
+    #[test]
+    fn test_best_lap() {
+        let mut timer = LapTimer::new();
+        drive(&mut timer, &[10.0, 110.0, 10.0, -10.0, 10.0]);
+        drive(&mut timer, &[110.0, -10.0, 10.0]);
+        assert_eq!(vec![5.0, 3.0], timer.lap_times);
+        assert_eq!(Some(3.0), timer.best_lap);
+        assert_eq!(Some(3.0), timer.last_lap());
+    }
+}
@@ -0,0 +1,216 @@
+use super::*;
+use super::settings::{UserSettings, KeyBindings, key_label};
+
+// the title sits this far above the middle of the canvas
+const MENU_TITLE_OFFSET: f64 = 100.0;
+const MENU_LINE_HEIGHT: f64 = 48.0;
+
+struct Menu {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+}
+
+impl Menu {
+    fn new(title: &str, items: Vec<String>) -> Self {
+        Menu { title: title.to_string(), items, selected: 0 }
+    }
+
+    fn navigate(&mut self, keyboard_state: &KeyboardState) {
+        if self.items.is_empty() {
+            return;
+        }
+        if keyboard_state.was_pressed("ArrowUp") {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+        if keyboard_state.was_pressed("ArrowDown") {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    // `size` is the size of the canvas, the menu is centered on it
+    fn draw(&self, renderer: &Renderer, size: FVec) {
+        let (x, top) = (size.x / 2.0, size.y / 2.0 - MENU_TITLE_OFFSET);
+        renderer.save();
+        renderer.text_align(TextAlign::Center);
+        renderer.text_baseline(TextBaseline::Middle);
+        renderer.fill_style("black");
+        renderer.font("bold 36px sans-serif");
+        renderer.fill_text(&self.title, FVec::new(x, top));
+        renderer.font("24px sans-serif");
+        for (i, item) in self.items.iter().enumerate() {
+            let y = top + MENU_LINE_HEIGHT * (i + 1) as f64;
+            if i == self.selected {
+                let width = renderer.measure_text(item) + 40.0;
+                renderer.fill_style("rgba(0, 0, 0, 0.15)");
+                renderer.rounded_rect(&Rect { x: x - width / 2.0, y: y - MENU_LINE_HEIGHT / 2.0 + 4.0, w: width, h: MENU_LINE_HEIGHT - 8.0 }, 8.0, true);
+                renderer.fill_style("black");
+            }
+            renderer.fill_text(item, FVec::new(x, y));
+        }
+        renderer.restore();
+    }
+}
+
+pub struct MainMenuStage {
+    menu: Menu,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    size: FVec,
+}
+
+impl MainMenuStage {
+    pub fn new(keyboard_state: Rc<RefCell<KeyboardState>>, size: FVec) -> Box<Self> {
+        Box::new(MainMenuStage {
+            menu: Menu::new("Racing Game", vec!["Race".to_string(), "Train AI".to_string(), "Track Editor".to_string(), "Controls".to_string()]),
+            keyboard_state,
+            size,
+        })
+    }
+}
+
+impl Stage for MainMenuStage {
+    fn update(&mut self, _delta: f64) -> Transition {
+        let keyboard_state = self.keyboard_state.borrow();
+        self.menu.navigate(&keyboard_state);
+        if keyboard_state.was_pressed("Enter") {
+            return Transition::Push(match self.menu.selected {
+                0 => StageRequest::TrackSelect(GameMode::Race),
+                1 => StageRequest::TrackSelect(GameMode::Training),
+                2 => StageRequest::TrackEditor,
+                _ => StageRequest::Controls,
+            });
+        }
+        Transition::None
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        self.menu.draw(renderer, self.size);
+    }
+}
+
+pub struct TrackSelectStage {
+    mode: GameMode,
+    menu: Menu,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    settings: Rc<RefCell<UserSettings>>,
+    size: FVec,
+}
+
+impl TrackSelectStage {
+    pub fn new(keyboard_state: Rc<RefCell<KeyboardState>>, settings: Rc<RefCell<UserSettings>>, tracks: Rc<Vec<TrackFile>>, mode: GameMode, size: FVec) -> Box<Self> {
+        let names: Vec<String> = tracks.iter().map(|track| track.name.clone()).collect();
+        let last_track = settings.borrow().last_track.clone();
+        let selected = last_track.and_then(|last| names.iter().position(|name| *name == last));
+        let mut menu = Menu::new("Select track", names);
+        if let Some(index) = selected {
+            menu.selected = index;
+        }
+        Box::new(TrackSelectStage {
+            mode,
+            menu,
+            keyboard_state,
+            settings,
+            size,
+        })
+    }
+}
+
+impl Stage for TrackSelectStage {
+    fn update(&mut self, _delta: f64) -> Transition {
+        let keyboard_state = self.keyboard_state.borrow();
+        self.menu.navigate(&keyboard_state);
+        if keyboard_state.was_pressed("Escape") {
+            return Transition::Pop;
+        }
+        if keyboard_state.was_pressed("Enter") {
+            let track_index = self.menu.selected;
+            let mut settings = self.settings.borrow_mut();
+            settings.last_track = Some(self.menu.items[track_index].clone());
+            save_settings(&settings);
+            return Transition::Switch(match self.mode {
+                GameMode::Race => StageRequest::Race(track_index),
+                GameMode::Training => StageRequest::Training(track_index),
+            });
+        }
+        Transition::None
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        self.menu.draw(renderer, self.size);
+    }
+}
+
+// lists the key bindings, Enter on one waits for the key to bind to it
+pub struct ControlsStage {
+    menu: Menu,
+    waiting: bool,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    settings: Rc<RefCell<UserSettings>>,
+    size: FVec,
+}
+
+impl ControlsStage {
+    pub fn new(keyboard_state: Rc<RefCell<KeyboardState>>, settings: Rc<RefCell<UserSettings>>, size: FVec) -> Box<Self> {
+        let mut stage = ControlsStage {
+            menu: Menu::new("Controls", vec![]),
+            waiting: false,
+            keyboard_state,
+            settings,
+            size,
+        };
+        stage.refresh();
+        Box::new(stage)
+    }
+
+    fn refresh(&mut self) {
+        let settings = self.settings.borrow();
+        self.menu.items = KeyBindings::ACTIONS.iter().zip(settings.key_bindings.keys().iter()).enumerate()
+            .map(|(index, (action, key))| {
+                let key = if self.waiting && index == self.menu.selected { "press a key".to_string() } else { key_label(key) };
+                format!("{}: {}", action, key)
+            })
+            .collect();
+    }
+}
+
+impl Stage for ControlsStage {
+    // while waiting every key is for the binding, the debug overlay must not take it
+    fn claims_key(&self, _code: &str) -> bool {
+        self.waiting
+    }
+
+    fn update(&mut self, _delta: f64) -> Transition {
+        let pressed = self.keyboard_state.borrow().any_pressed().map(|code| code.to_string());
+        if self.waiting {
+            match pressed.as_deref() {
+                None => return Transition::None,
+                Some("Escape") => {},
+                Some(code) => {
+                    let mut settings = self.settings.borrow_mut();
+                    settings.key_bindings.bind(self.menu.selected, code);
+                    save_settings(&settings);
+                },
+            }
+            self.waiting = false;
+            self.refresh();
+            return Transition::None;
+        }
+        let (back, choose) = {
+            let keyboard_state = self.keyboard_state.borrow();
+            self.menu.navigate(&keyboard_state);
+            (keyboard_state.was_pressed("Escape"), keyboard_state.was_pressed("Enter"))
+        };
+        if back {
+            return Transition::Pop;
+        }
+        if choose {
+            self.waiting = true;
+            self.refresh();
+        }
+        Transition::None
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        self.menu.draw(renderer, self.size);
+    }
+}
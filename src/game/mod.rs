@@ -1,13 +1,39 @@
-use crate::{engine::*, browser::prepare_input};
+use crate::{engine::*, browser::{prepare_input, prepare_mouse_input}};
 use crate::browser::*;
 use std::any::Any;
 mod car;
 mod car_sensor;
 mod car_controller;
+mod vehicle;
 mod stage;
+mod stage_manager;
+mod menu_stage;
+mod results_stage;
+mod error_stage;
 mod track;
+mod track_file;
+mod spline;
+mod track_editor;
+mod track_generator;
+mod surface;
+mod damage;
+mod consumables;
+mod pickup;
 mod score;
+mod lap;
+mod hud;
+mod debug;
+mod settings;
 use stage::*;
+use stage_manager::*;
+use debug::*;
+use settings::*;
+use crate::browser::storage;
+pub use error_stage::show_error;
+use crate::error::GameError;
+use crate::config::GameConfig;
+pub use track_file::{TrackFile, load_tracks, TRACKS_URL};
+pub use track_generator::generate_tracks;
 use futures::channel::mpsc::*;
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -19,40 +45,122 @@ pub trait GameObject: Any {
     fn as_mut_any(&mut self) -> &mut dyn Any;
 }
 
+pub fn save_settings(settings: &UserSettings) {
+    if let Err(err) = storage::save(settings) {
+        crate::log_warn!("can not save settings: {}", err);
+    }
+}
+
+pub fn save_records(records: &PlayerRecords) {
+    if let Err(err) = storage::save(records) {
+        crate::log_warn!("can not save lap records: {}", err);
+    }
+}
+
 pub struct RacingGame {
     pub width: f64,
     pub height: f64,
     pub keyboard_state: Rc<RefCell<KeyboardState>>,
+    pub mouse_state: Rc<RefCell<MouseState>>,
     receiver: UnboundedReceiver<KeyPress>,
-    current_stage: Option<Box<GameStage>>,
+    mouse_receiver: UnboundedReceiver<MouseInput>,
+    stages: StageManager,
+    debug: DebugFlags,
+    frame_stats: FrameStats,
+    settings: Rc<RefCell<UserSettings>>,
 }
 
 impl RacingGame {
-    pub async fn new(width: f64, height: f64) -> Self {
-        let receiver = prepare_input().unwrap();
+    pub async fn new(width: f64, height: f64, config: GameConfig, tracks: Vec<TrackFile>) -> Result<Self, GameError> {
+        let receiver = prepare_input()?;
+        let mouse_receiver = prepare_mouse_input()?;
         let keyboard_state = Rc::new(RefCell::new(KeyboardState::new()));
-        RacingGame {
+        let mouse_state = Rc::new(RefCell::new(MouseState::new()));
+        let settings = Rc::new(RefCell::new(storage::load::<UserSettings>()));
+        let debug = settings.borrow().debug;
+        Ok(RacingGame {
             width,
             height,
             receiver,
+            mouse_receiver,
             keyboard_state: keyboard_state.clone(),
-            current_stage: Some(GameStage::new(keyboard_state.clone()).await),
-        }
+            mouse_state: mouse_state.clone(),
+            stages: StageManager::new(keyboard_state.clone(), mouse_state, Rc::new(config), settings.clone(), Rc::new(tracks), FVec::new(width, height)),
+            debug,
+            frame_stats: FrameStats::new(),
+            settings,
+        })
     }
 }
 
 impl RacingGame {
     pub fn draw(&mut self, renderer: &Renderer) {
         renderer.clear(&Rect{ x: 0.0, y:0.0, w: self.width, h: self.height });
-        if let Some(stage) = &self.current_stage {
-            stage.draw(renderer);
+        self.frame_stats.frame(now());
+        profiler::profile(profiler::Phase::Drawing, || self.stages.draw(renderer));
+        if self.debug.enabled {
+            if let Some(stage) = self.stages.current() {
+                stage.draw_debug(renderer, &self.debug);
+            }
+            if self.debug.stats {
+                self.frame_stats.draw(renderer, self.width);
+            }
+            if self.debug.profiler {
+                draw_profile(renderer, self.width);
+            }
+        }
+        if let Some(hud) = self.stages.current().and_then(|stage| stage.hud()) {
+            hud.draw(renderer);
+        }
+        if self.stages.current().map_or(false, |stage| stage.is_paused()) {
+            self.draw_pause_overlay(renderer);
+        }
+        profiler::end_frame();
+    }
+
+    fn draw_pause_overlay(&self, renderer: &Renderer) {
+        renderer.save();
+        renderer.global_alpha(0.5);
+        renderer.fill(&Rect{ x: 0.0, y: 0.0, w: self.width, h: self.height }, "black");
+        renderer.global_alpha(1.0);
+        renderer.fill_style("white");
+        renderer.text_align(TextAlign::Center);
+        renderer.font("bold 36px sans-serif");
+        renderer.fill_text("Paused", FVec::new(self.width / 2.0, self.height / 2.0));
+        renderer.font("18px sans-serif");
+        let hint = {
+            let key_bindings = &self.settings.borrow().key_bindings;
+            format!("press {} to resume, {} to step", key_label(&key_bindings.pause), key_label(&key_bindings.step))
+        };
+        renderer.fill_text(&hint, FVec::new(self.width / 2.0, self.height / 2.0 + 36.0));
+        renderer.restore();
+    }
+
+    pub fn on_window_event(&mut self, event: WindowEvent) {
+        match event {
+            WindowEvent::Blur | WindowEvent::Hidden => {
+                self.keyboard_state.borrow_mut().release_all();
+                self.stages.pause();
+            },
+            WindowEvent::Visible => {},
         }
     }
 
     pub fn update(&mut self, delta: f64) {
-        process_input(&mut self.receiver, self.keyboard_state.clone());
-        if let Some(ref mut stage) = self.current_stage {
-            stage.update(delta);
+        profiler::profile(profiler::Phase::Input, || {
+            process_input(&mut self.receiver, self.keyboard_state.clone());
+            process_mouse_input(&mut self.mouse_receiver, self.mouse_state.clone());
+        });
+        let debug = self.debug;
+        let stage = self.stages.current();
+        self.debug.handle_input(&self.keyboard_state.borrow(), |code| stage.map_or(false, |stage| stage.claims_key(code)));
+        if debug != self.debug {
+            self.settings.borrow_mut().debug = self.debug;
+            save_settings(&self.settings.borrow());
         }
+        self.frame_stats.tick();
+        self.stages.update(delta);
+        self.keyboard_state.borrow_mut().clear_just_pressed();
+        self.mouse_state.borrow_mut().clear_clicks();
     }
 }
@@ -0,0 +1,212 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use super::*;
+use super::car::{Car, CarStatus};
+use super::vehicle::VehicleParams;
+
+const PICKUP_SIZE: f64 = 24.0;
+const RESPAWN_TIME: f64 = 8.0;
+const BOOST_ENGINE: f64 = 1.6;
+const BOOST_DRAG: f64 = 0.6;
+const OIL_GRIP: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickupKind {
+    SpeedBoost,
+    Shield,
+    OilSlick,
+}
+
+impl PickupKind {
+    pub fn duration(&self) -> f64 {
+        match self {
+            PickupKind::SpeedBoost => 3.0,
+            PickupKind::Shield => 5.0,
+            PickupKind::OilSlick => 2.0,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PickupKind::SpeedBoost => "Boost",
+            PickupKind::Shield => "Shield",
+            PickupKind::OilSlick => "Oil",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            PickupKind::SpeedBoost => "#ffb000",
+            PickupKind::Shield => "#3fa9f5",
+            PickupKind::OilSlick => "#222222",
+        }
+    }
+}
+
+// where a track places a pickup, `segment` indexes the track segments
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PickupSpot {
+    pub segment: usize,
+    pub kind: PickupKind,
+}
+
+// the effects a car has picked up and how long each one has left
+#[derive(Debug, Default, Clone)]
+pub struct Effects {
+    active: Vec<(PickupKind, f64)>,
+}
+
+impl Effects {
+    pub fn new() -> Self {
+        Effects::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.active.clear();
+    }
+
+    // picking up an effect that is still running starts it over
+    pub fn add(&mut self, kind: PickupKind) {
+        self.active.retain(|(active, _)| *active != kind);
+        self.active.push((kind, kind.duration()));
+    }
+
+    pub fn tick(&mut self, delta: f64) {
+        for (_, remaining) in self.active.iter_mut() {
+            *remaining -= delta;
+        }
+        self.active.retain(|(_, remaining)| *remaining > 0.0);
+    }
+
+    pub fn has(&self, kind: PickupKind) -> bool {
+        self.active.iter().any(|(active, _)| *active == kind)
+    }
+
+    pub fn active(&self) -> &[(PickupKind, f64)] {
+        &self.active
+    }
+
+    pub fn apply_to(&self, params: &VehicleParams) -> VehicleParams {
+        let mut params = params.clone();
+        if self.has(PickupKind::SpeedBoost) {
+            params.engine_force *= BOOST_ENGINE;
+            params.drag *= BOOST_DRAG;
+        }
+        if self.has(PickupKind::OilSlick) {
+            params.grip *= OIL_GRIP;
+        }
+        params
+    }
+}
+
+#[derive(Debug)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub body: BoundingBox,
+    // time until a collected pickup shows up again
+    respawn: Option<f64>,
+}
+
+impl Pickup {
+    pub fn new(kind: PickupKind, center: &FVec) -> Self {
+        let rect = Rect { x: center.x - PICKUP_SIZE / 2.0, y: center.y - PICKUP_SIZE / 2.0, w: PICKUP_SIZE, h: PICKUP_SIZE };
+        Pickup { kind, body: BoundingBox::new(&rect), respawn: None }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.respawn.is_none()
+    }
+
+    fn tick(&mut self, delta: f64) {
+        if let Some(remaining) = self.respawn {
+            self.respawn = Some(remaining - delta).filter(|remaining| *remaining > 0.0);
+        }
+    }
+
+    fn collect(&mut self, car: &mut Car) {
+        crate::log_debug!("car {} picked up {:?}", car.id, self.kind);
+        car.effects.add(self.kind);
+        self.respawn = Some(RESPAWN_TIME);
+    }
+}
+
+impl GameObject for Pickup {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        if !self.is_available() {
+            return;
+        }
+        if let Some(center) = self.body.get_center() {
+            renderer.save();
+            renderer.fill_style(self.kind.color());
+            renderer.arc(center.x, center.y, PICKUP_SIZE / 2.0, 0.0, PI * 2.0, true);
+            renderer.fill_style("white");
+            renderer.font("bold 12px sans-serif");
+            renderer.text_align(TextAlign::Center);
+            renderer.text_baseline(TextBaseline::Middle);
+            renderer.fill_text(&self.kind.label()[..1], center);
+            renderer.restore();
+        }
+    }
+
+    fn update(&mut self, stage: &mut GameStage, delta: f64) {
+        self.tick(delta);
+        if !self.is_available() {
+            return;
+        }
+        let car = stage.find_mut::<Car>().into_iter()
+            .find(|car| car.status == CarStatus::Live && car.body.overlaps(&self.body));
+        if let Some(car) = car {
+            self.collect(car);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CarConfig;
+
+    #[test]
+    fn test_effects_expire() {
+        let mut effects = Effects::new();
+        effects.add(PickupKind::SpeedBoost);
+        effects.add(PickupKind::OilSlick);
+        effects.tick(PickupKind::OilSlick.duration() + 0.1);
+        assert!(effects.has(PickupKind::SpeedBoost));
+        assert!(!effects.has(PickupKind::OilSlick), "oil should have worn off");
+        effects.add(PickupKind::SpeedBoost);
+        assert_eq!(1, effects.active().len(), "picking up again should refresh, not stack");
+        assert_eq!(PickupKind::SpeedBoost.duration(), effects.active()[0].1);
+    }
+
+    #[test]
+    fn test_effects_change_physics() {
+        let params = VehicleParams::from_config(&CarConfig::default());
+        let mut effects = Effects::new();
+        assert_eq!(params, effects.apply_to(&params));
+        effects.add(PickupKind::SpeedBoost);
+        effects.add(PickupKind::OilSlick);
+        let changed = effects.apply_to(&params);
+        assert!(changed.engine_force > params.engine_force);
+        assert!(changed.grip < params.grip);
+    }
+
+    #[test]
+    fn test_respawn() {
+        let mut pickup = Pickup::new(PickupKind::Shield, &FVec::new(0.0, 0.0));
+        pickup.respawn = Some(RESPAWN_TIME);
+        pickup.tick(RESPAWN_TIME - 1.0);
+        assert!(!pickup.is_available());
+        pickup.tick(1.5);
+        assert!(pickup.is_available());
+    }
+}
@@ -0,0 +1,66 @@
+use super::*;
+use super::hud::format_time;
+
+pub struct ResultsStage {
+    results: RaceResults,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    // the size of the canvas
+    size: FVec,
+}
+
+impl ResultsStage {
+    pub fn new(keyboard_state: Rc<RefCell<KeyboardState>>, results: RaceResults, size: FVec) -> Box<Self> {
+        Box::new(ResultsStage { results, keyboard_state, size })
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Track: {}", self.results.track_name)];
+        match self.results.mode {
+            GameMode::Race => {
+                lines.push(format!("Score: {:.0}", self.results.best_score));
+                lines.push(format!("Laps: {}", self.results.laps));
+                if let Some(best_lap) = self.results.best_lap {
+                    lines.push(format!("Best lap: {}", format_time(best_lap)));
+                }
+                if self.results.new_record {
+                    lines.push("New lap record!".to_string());
+                }
+                lines.push(format!("Time: {:.2}s", self.results.elapsed));
+            },
+            GameMode::Training => {
+                lines.push(format!("Generations: {}", self.results.rounds));
+                lines.push(format!("Best score: {:.0}", self.results.best_score));
+                if let Some(best_lap) = self.results.best_lap {
+                    lines.push(format!("Best lap: {}", format_time(best_lap)));
+                }
+                lines.push(format!("Training time: {:.0}s", self.results.elapsed));
+            },
+        }
+        lines.push("Press Enter to continue".to_string());
+        lines
+    }
+}
+
+impl Stage for ResultsStage {
+    fn update(&mut self, _delta: f64) -> Transition {
+        let keyboard_state = self.keyboard_state.borrow();
+        if keyboard_state.was_pressed("Enter") || keyboard_state.was_pressed("Escape") {
+            return Transition::Pop;
+        }
+        Transition::None
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        let (x, top) = (self.size.x / 2.0, self.size.y / 2.0 - 100.0);
+        renderer.save();
+        renderer.text_align(TextAlign::Center);
+        renderer.fill_style("black");
+        renderer.font("bold 36px sans-serif");
+        renderer.fill_text("Results", FVec::new(x, top));
+        renderer.font("20px sans-serif");
+        for (i, line) in self.lines().iter().enumerate() {
+            renderer.fill_text(line, FVec::new(x, top + 50.0 + 32.0 * i as f64));
+        }
+        renderer.restore();
+    }
+}
@@ -1,15 +1,6 @@
 use std::collections::HashMap;
 use super::*;
 
-const UP: FVec = FVec::new(0.0, -1.0);
-const UP_RIGHT: FVec = FVec::new(1.0, -1.0);
-const RIGHT: FVec = FVec::new(1.0, 0.0);
-const DOWN_RIGHT: FVec = FVec::new(1.0, 1.0);
-const DOWN: FVec = FVec::new(0.0, 1.0);
-const DOWN_LEFT: FVec = FVec::new(-1.0, 1.0);
-const LEFT: FVec = FVec::new(-1.0, 0.0);
-const UP_LEFT: FVec = FVec::new(-1.0, -1.0);
-
 #[derive(Debug)]
 pub struct Score {
     pub top_score: f64,
@@ -32,6 +23,11 @@ impl Score {
         self.stale_time > time
     }
 
+    // only training ends the run of a car that stops making progress, in a race running wide just costs time
+    pub fn stalls_out(&self, mode: GameMode, stale_timeout: f64) -> bool {
+        mode == GameMode::Training && self.is_stale_for(stale_timeout)
+    }
+
     pub fn reset(&mut self) {
         self.position.clear();
         self.top_score = 0.0;
@@ -39,8 +35,12 @@ impl Score {
         self.stale_time = 0.0
     }
 
-    pub fn update(&mut self, car_body: &BoundingBox, track: &track::Track, delta: f64) {
-        use track::TrackSegmentDirection::*;
+    // a car `serviced` in the pit lane is held still, so it is not stale while it waits
+    pub fn update(&mut self, car_body: &BoundingBox, track: &track::Track, serviced: bool, delta: f64) {
+        if serviced {
+            self.stale_time = 0.0;
+            return;
+        }
         let current_pos = car_body.get_center();
         let mut diff = FVec::new(0.0, 0.0);
         if let Some(seg) = track.on_which_track_seg(car_body) {
@@ -50,20 +50,7 @@ impl Score {
                 } 
                 self.position.insert(seg.id, current_pos);
             }
-            let update_score = match seg.direction {
-                Up => diff.dot(&UP),
-                UpRightRight => diff.dot(&UP_RIGHT),
-                UpRightUp => diff.dot(&UP_RIGHT),
-                Right => diff.dot(&RIGHT),
-                DownRightRight => diff.dot(&DOWN_RIGHT),
-                DownRightDown => diff.dot(&DOWN_RIGHT),
-                Down => diff.dot(&DOWN),
-                DownLeftLeft => diff.dot(&DOWN_LEFT),
-                DownLeftDown => diff.dot(&DOWN_LEFT),
-                Left => diff.dot(&LEFT),
-                UpLeftLeft => diff.dot(&UP_LEFT),
-                UpLeftUp => diff.dot(&UP_LEFT),
-            };
+            let update_score = diff.dot(&seg.heading);
             self.score += update_score;
             if self.score > self.top_score {
                 self.top_score = self.score;
@@ -71,6 +58,91 @@ impl Score {
             } else {
                 self.stale_time += delta;
             }
+        } else {
+            // no progress is made off the track
+            self.stale_time += delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::consumables::Consumables;
+    use super::super::spline::ControlPoint;
+    use super::super::surface::Surface;
+    use crate::config::{CarConfig, GameConfig};
+
+    const DELTA: f64 = 1.0 / 60.0;
+
+    // halfway along its top the road of this square runs right through (200, -50)
+    fn square_track() -> track::Track {
+        let point = |x: f64, y: f64| ControlPoint { x, y, width: 100.0, surface: Surface::Asphalt };
+        track::Track::from_spline(&[point(0.0, 0.0), point(400.0, 0.0), point(400.0, 400.0), point(0.0, 400.0)], 25.0, None).0.unwrap()
+    }
+
+    fn tile_track() -> track::Track {
+        let directions = ["right", "down_right_down", "down_left_left", "left", "up_left_up", "up_right_right"].iter()
+            .map(|name| track::TrackSegmentDirection::from_name(name).unwrap())
+            .collect();
+        track::Track::from_tiles(0.0, 0.0, directions, None).unwrap()
+    }
+
+    fn car_at(x: f64, y: f64) -> BoundingBox {
+        BoundingBox::new_with_origin(&Rect { x, y, w: 20.0, h: 10.0 }, FVec::new(0.0, 5.0))
+    }
+
+    // the progress made driving `distance` along the segment under a car at `x`, `y`
+    fn progress(track: &track::Track, x: f64, y: f64, distance: f64) -> f64 {
+        let mut body = car_at(x, y);
+        let mut score = Score::new();
+        score.update(&body, track, false, DELTA);
+        let heading = track.on_which_track_seg(&body).unwrap().heading;
+        body.move_by(&FVec::new(heading.x * distance, heading.y * distance));
+        score.update(&body, track, false, DELTA);
+        score.score
+    }
+
+    #[test]
+    fn test_progress_on_every_track() {
+        let (tiles, square) = (tile_track(), square_track());
+        for (name, track, x, y) in [("straight tile", &tiles, 60.0, 70.0), ("corner tile", &tiles, 210.0, 70.0), ("spline", &square, 200.0, -50.0)].iter() {
+            let driven = progress(track, *x, *y, 10.0);
+            assert!((driven - 10.0).abs() < 1e-9, "driving 10 on a {} should make 10 progress, made {}", name, driven);
+        }
+    }
+
+    #[test]
+    fn test_pit_stop_is_not_stale() {
+        let config = GameConfig::default();
+        let (track, body) = (square_track(), car_at(200.0, -50.0));
+        let mut score = Score::new();
+        let mut consumables = Consumables::new(&CarConfig::default());
+        assert!(consumables.update_pit(true, 0.0, config.race.pit_stop_time, DELTA));
+        let mut serviced_for = 0.0;
+        while consumables.pit_stop.is_some() {
+            score.update(&body, &track, consumables.pit_stop.is_some(), DELTA);
+            consumables.update_pit(true, 0.0, config.race.pit_stop_time, DELTA);
+            assert!(!score.is_stale_for(config.training.stale_timeout), "the car should survive its stop, stopped for {:.2}s", serviced_for);
+            serviced_for += DELTA;
+        }
+        assert!(serviced_for > config.training.stale_timeout, "the stop should outlast the stale timeout");
+        score.update(&body, &track, false, DELTA);
+        assert!(!score.is_stale_for(config.training.stale_timeout), "leaving the pit lane starts the clock again");
+    }
+
+    #[test]
+    fn test_off_track_race_car_survives() {
+        let config = GameConfig::default();
+        let track = square_track();
+        let mut score = Score::new();
+        // the middle of the square is grass
+        let mut body = car_at(150.0, 200.0);
+        for _ in 0..(config.training.stale_timeout * 2.0 / DELTA) as usize {
+            body.move_by(&FVec::new(0.5, 0.0));
+            score.update(&body, &track, false, DELTA);
         }
+        assert!(!score.stalls_out(GameMode::Race, config.training.stale_timeout), "a race car off the track only loses time");
+        assert!(score.stalls_out(GameMode::Training, config.training.stale_timeout), "a training car off the track is out");
     }
 }
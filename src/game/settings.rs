@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::browser::storage::Persisted;
+use super::debug::DebugFlags;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub left: String,
+    pub right: String,
+    pub accelerate: String,
+    pub brake: String,
+    pub handbrake: String,
+    pub pause: String,
+    pub step: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: "ArrowLeft".to_string(),
+            right: "ArrowRight".to_string(),
+            accelerate: "ArrowUp".to_string(),
+            brake: "ArrowDown".to_string(),
+            handbrake: "Space".to_string(),
+            pause: "KeyP".to_string(),
+            step: "Period".to_string(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub const ACTIONS: [&'static str; 7] = ["Steer left", "Steer right", "Accelerate", "Brake", "Handbrake", "Pause", "Step"];
+
+    // the key of each of `ACTIONS`, in the same order
+    pub fn keys(&self) -> [&str; 7] {
+        [&self.left, &self.right, &self.accelerate, &self.brake, &self.handbrake, &self.pause, &self.step]
+    }
+
+    fn keys_mut(&mut self) -> [&mut String; 7] {
+        [&mut self.left, &mut self.right, &mut self.accelerate, &mut self.brake, &mut self.handbrake, &mut self.pause, &mut self.step]
+    }
+
+    // binds `code` to the action at `index`, an action that had `code` before gets the old key so no key does two things
+    pub fn bind(&mut self, index: usize, code: &str) {
+        let mut keys = self.keys_mut();
+        let old = std::mem::replace(&mut *keys[index], code.to_string());
+        for (other, key) in keys.iter_mut().enumerate() {
+            if other != index && **key == code {
+                **key = old.clone();
+            }
+        }
+    }
+}
+
+// bindings hold `KeyboardEvent.code` values, this is how one reads on a key cap
+pub fn key_label(code: &str) -> String {
+    if let Some(key) = code.strip_prefix("Key").or_else(|| code.strip_prefix("Digit")) {
+        return key.to_string();
+    }
+    match code {
+        "Period" => ".",
+        "Comma" => ",",
+        "Slash" => "/",
+        "Semicolon" => ";",
+        "Minus" => "-",
+        "Equal" => "=",
+        code => code,
+    }.to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct UserSettings {
+    pub key_bindings: KeyBindings,
+    pub debug: DebugFlags,
+    pub last_track: Option<String>,
+}
+
+impl Persisted for UserSettings {
+    const KEY: &'static str = "racing_game.settings";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PlayerRecords {
+    pub best_laps: HashMap<String, f64>,
+}
+
+impl PlayerRecords {
+    // returns true when the lap is a new record for the track
+    pub fn record_lap(&mut self, track: &str, time: f64) -> bool {
+        match self.best_laps.get(track) {
+            Some(best) if *best <= time => false,
+            _ => {
+                self.best_laps.insert(track.to_string(), time);
+                true
+            },
+        }
+    }
+
+    pub fn best_lap(&self, track: &str) -> Option<f64> {
+        self.best_laps.get(track).cloned()
+    }
+}
+
+impl Persisted for PlayerRecords {
+    const KEY: &'static str = "racing_game.records";
+    const VERSION: u32 = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::storage::{encode, decode};
+
+    #[test]
+    fn test_record_lap() {
+        let mut records = PlayerRecords::default();
+        assert!(records.record_lap("oval", 30.0));
+        assert!(!records.record_lap("oval", 31.0), "slower lap is not a record");
+        assert!(records.record_lap("oval", 29.5));
+        assert_eq!(Some(29.5), records.best_lap("oval"));
+        assert_eq!(None, records.best_lap("classic"));
+    }
+
+    #[test]
+    fn test_key_label() {
+        assert_eq!("P", key_label("KeyP"));
+        assert_eq!("1", key_label("Digit1"));
+        assert_eq!(".", key_label("Period"));
+        assert_eq!("Space", key_label("Space"));
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(0, "KeyA");
+        assert_eq!("KeyA", bindings.left);
+        bindings.bind(5, "ArrowUp");
+        assert_eq!("ArrowUp", bindings.pause);
+        assert_eq!("KeyP", bindings.accelerate, "the key taken from accelerate should move over to it");
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let mut settings = UserSettings::default();
+        settings.last_track = Some("oval".to_string());
+        settings.key_bindings.left = "KeyA".to_string();
+        assert_eq!(settings, decode::<UserSettings>(&encode(&settings).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_settings_without_volume() {
+        // settings saved before the volume was dropped still load
+        let stored = r#"{ "version": 1, "data": { "volume": 0.5, "last_track": "oval" } }"#;
+        assert_eq!(Some("oval".to_string()), decode::<UserSettings>(stored).unwrap().last_track);
+    }
+}
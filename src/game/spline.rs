@@ -0,0 +1,201 @@
+use std::f64::consts::PI;
+use serde::{Serialize, Deserialize};
+use crate::engine::{FVec, Line};
+use crate::error::TrackIssue;
+use super::surface::Surface;
+
+// how long the pieces of the sampled centerline are at most, in pixels
+pub const DEFAULT_RESOLUTION: f64 = 25.0;
+const MIN_POINTS: usize = 3;
+
+// `width` is the width of the road at this point, it changes smoothly to the next point
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ControlPoint {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    #[serde(default)]
+    pub surface: Surface,
+}
+
+// a point on the sampled centerline with the road edges either side of it, `span` is the control point it follows
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub center: FVec,
+    pub left: FVec,
+    pub right: FVec,
+    pub span: usize,
+}
+
+// closed Catmull-Rom curve, `t` runs from `p1` to `p2`
+fn catmull_rom(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> (f64, f64) {
+    let a = 2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3;
+    let b = -p0 + 3.0 * p1 - 3.0 * p2 + p3;
+    let value = 0.5 * (2.0 * p1 + (p2 - p0) * t + a * t * t + b * t * t * t);
+    let slope = 0.5 * ((p2 - p0) + 2.0 * a * t + 3.0 * b * t * t);
+    (value, slope)
+}
+
+// walks the closed curve through `points`, every span is cut into pieces of at most `resolution`
+pub fn sample(points: &[ControlPoint], resolution: f64) -> Vec<Sample> {
+    let count = points.len();
+    let mut samples = vec![];
+    if count < MIN_POINTS || resolution <= 0.0 {
+        return samples;
+    }
+    for span in 0..count {
+        let (p0, p1, p2, p3) = (&points[(span + count - 1) % count], &points[span], &points[(span + 1) % count], &points[(span + 2) % count]);
+        let chord = FVec::new(p1.x, p1.y).distance(&FVec::new(p2.x, p2.y));
+        let pieces = ((chord / resolution).ceil() as usize).max(1);
+        for piece in 0..pieces {
+            let t = piece as f64 / pieces as f64;
+            let (x, dx) = catmull_rom(p0.x, p1.x, p2.x, p3.x, t);
+            let (y, dy) = catmull_rom(p0.y, p1.y, p2.y, p3.y, t);
+            let length = (dx * dx + dy * dy).sqrt();
+            let (dx, dy) = if length > 0.0 { (dx / length, dy / length) } else { (1.0, 0.0) };
+            let half = (p1.width + (p2.width - p1.width) * t) / 2.0;
+            // y points down, so the driver's left of a heading (dx, dy) is (dy, -dx)
+            samples.push(Sample {
+                center: FVec::new(x, y),
+                left: FVec::new(x + dy * half, y - dx * half),
+                right: FVec::new(x - dy * half, y + dx * half),
+                span,
+            });
+        }
+    }
+    samples
+}
+
+// how far the road turns over span `span` in radians, every bend counts whichever way it goes
+pub fn span_turn(points: &[ControlPoint], span: usize, resolution: f64) -> f64 {
+    let samples = sample(points, resolution);
+    let angles: Vec<f64> = (0..samples.len())
+        .filter(|index| samples[*index].span == span)
+        .map(|index| {
+            let (from, to) = (samples[index].center, samples[(index + 1) % samples.len()].center);
+            (to.y - from.y).atan2(to.x - from.x)
+        })
+        .collect();
+    angles.windows(2)
+        .map(|pair| {
+            let turn = (pair[1] - pair[0]).rem_euclid(2.0 * PI);
+            if turn > PI { 2.0 * PI - turn } else { turn }
+        })
+        .sum()
+}
+
+// an edge turns back on itself where a bend is tighter than half the road is wide
+fn folds(from: &FVec, to: &FVec, along: &FVec) -> bool {
+    (to.x - from.x) * along.x + (to.y - from.y) * along.y <= 0.0
+}
+
+pub fn spline_issues(points: &[ControlPoint], resolution: f64) -> Vec<TrackIssue> {
+    let mut issues = vec![];
+    if points.len() < MIN_POINTS {
+        return vec![TrackIssue::TooFewPoints];
+    }
+    for (point, control) in points.iter().enumerate() {
+        if !control.width.is_finite() || control.width <= 0.0 || !control.x.is_finite() || !control.y.is_finite() {
+            issues.push(TrackIssue::InvalidPoint { point });
+        }
+    }
+    if !resolution.is_finite() || resolution <= 0.0 {
+        issues.push(TrackIssue::InvalidResolution);
+    }
+    if !issues.is_empty() {
+        return issues;
+    }
+    let samples = sample(points, resolution);
+    let next = |index: usize| &samples[(index + 1) % samples.len()];
+    for (index, current) in samples.iter().enumerate() {
+        let along = FVec::new(next(index).center.x - current.center.x, next(index).center.y - current.center.y);
+        let tight = TrackIssue::TooTight { point: current.span };
+        if (folds(&current.left, &next(index).left, &along) || folds(&current.right, &next(index).right, &along)) && !issues.contains(&tight) {
+            issues.push(tight);
+        }
+    }
+    // the centerline may not cross itself, neighbouring pieces always touch so they are skipped
+    let pieces: Vec<(usize, Line)> = samples.iter().enumerate()
+        .map(|(index, current)| (current.span, Line { start: current.center, end: next(index).center }))
+        .collect();
+    for (index, (span, piece)) in pieces.iter().enumerate() {
+        for (other_index, (other_span, other)) in pieces.iter().enumerate().skip(index + 2) {
+            if index == 0 && other_index == pieces.len() - 1 {
+                continue;
+            }
+            let overlap = TrackIssue::Overlap { segment: *other_span, other: *span };
+            if piece.intersect(other).is_some() && !issues.contains(&overlap) {
+                issues.push(overlap);
+            }
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, width: f64) -> ControlPoint {
+        ControlPoint { x, y, width, surface: Surface::Asphalt }
+    }
+
+    fn square(width: f64) -> Vec<ControlPoint> {
+        vec![point(0.0, 0.0, width), point(400.0, 0.0, width), point(400.0, 400.0, width), point(0.0, 400.0, width)]
+    }
+
+    #[test]
+    fn test_sample_passes_through_points() {
+        let points = square(100.0);
+        let samples = sample(&points, DEFAULT_RESOLUTION);
+        assert!(samples.len() >= 4 * (400.0 / DEFAULT_RESOLUTION) as usize);
+        for (span, control) in points.iter().enumerate() {
+            let first = samples.iter().find(|sample| sample.span == span).unwrap();
+            assert!(first.center.distance(&FVec::new(control.x, control.y)) < 1e-9);
+            assert!((first.left.distance(&first.right) - control.width).abs() < 1e-9, "the road should be as wide as the point says");
+        }
+        for pair in samples.windows(2) {
+            assert!(pair[0].center.distance(&pair[1].center) <= DEFAULT_RESOLUTION * 1.5);
+        }
+    }
+
+    #[test]
+    fn test_left_is_left() {
+        // driving clockwise on screen the left edge is on the outside
+        let samples = sample(&square(100.0), DEFAULT_RESOLUTION);
+        let top = samples.iter().find(|sample| sample.span == 0 && sample.center.x > 150.0).unwrap();
+        assert!(top.left.y < top.center.y && top.right.y > top.center.y);
+    }
+
+    #[test]
+    fn test_variable_width() {
+        let mut points = square(100.0);
+        points[1].width = 200.0;
+        let samples = sample(&points, DEFAULT_RESOLUTION);
+        let middle = samples.iter().filter(|sample| sample.span == 0).nth(8).unwrap();
+        let width = middle.left.distance(&middle.right);
+        assert!(width > 100.0 && width < 200.0, "width should blend between the points, got {}", width);
+    }
+
+    #[test]
+    fn test_span_turn() {
+        // the second span has the points before and after it in line, so it is straight
+        let points = vec![point(0.0, 0.0, 50.0), point(200.0, 0.0, 50.0), point(400.0, 0.0, 50.0), point(600.0, 0.0, 50.0), point(600.0, 400.0, 50.0), point(0.0, 400.0, 50.0)];
+        assert!(span_turn(&points, 1, DEFAULT_RESOLUTION) < 1e-9);
+        let turn = span_turn(&points, 3, DEFAULT_RESOLUTION);
+        assert!(turn > PI / 4.0 && turn < PI, "the span into the corner turns, got {}", turn);
+    }
+
+    #[test]
+    fn test_issues() {
+        assert!(spline_issues(&square(100.0), DEFAULT_RESOLUTION).is_empty());
+        assert_eq!(vec![TrackIssue::TooFewPoints], spline_issues(&square(100.0)[..2], DEFAULT_RESOLUTION));
+        let mut points = square(100.0);
+        points[2].width = -1.0;
+        assert_eq!(vec![TrackIssue::InvalidPoint { point: 2 }], spline_issues(&points, DEFAULT_RESOLUTION));
+        assert!(spline_issues(&square(700.0), DEFAULT_RESOLUTION).iter().any(|issue| matches!(issue, TrackIssue::TooTight { .. })));
+        let crossing = vec![point(0.0, 0.0, 50.0), point(400.0, 400.0, 50.0), point(400.0, 0.0, 50.0), point(0.0, 400.0, 50.0)];
+        assert!(spline_issues(&crossing, DEFAULT_RESOLUTION).iter().any(|issue| matches!(issue, TrackIssue::Overlap { .. })));
+    }
+}
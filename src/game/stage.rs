@@ -2,11 +2,16 @@ use super::*;
 use super::car::*;
 use super::track::*;
 use super::car_controller::*;
+use super::hud::*;
+use super::debug::*;
+use super::settings::*;
+use super::pickup::Pickup;
+use crate::engine::profiler::{profile, Phase};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::vec;
-
-const NUM_CARS: u32 = 50;
+use crate::error::GameError;
+use crate::config::{CarConfig, SpawnConfig};
 
 #[derive(PartialEq)]
 pub enum GameStatus {
@@ -15,50 +20,185 @@ pub enum GameStatus {
     Over,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GameMode {
+    Race,
+    Training,
+}
+
+#[derive(Debug, Clone)]
+pub struct RaceResults {
+    pub mode: GameMode,
+    pub track_name: String,
+    pub rounds: i64,
+    pub best_score: f64,
+    pub elapsed: f64,
+    // laps the player drove, training counts none
+    pub laps: u32,
+    pub best_lap: Option<f64>,
+    pub new_record: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum StageRequest {
+    TrackSelect(GameMode),
+    Race(usize),
+    Training(usize),
+    TrackEditor,
+    Controls,
+    // races a track straight from the editor
+    TestDrive(TrackFile),
+    Results(RaceResults),
+}
+
+pub enum Transition {
+    None,
+    Push(StageRequest),
+    Switch(StageRequest),
+    Pop,
+}
+
 pub trait Stage {
-    fn update(&mut self, delta: f64);
+    // called whenever the stage becomes the top of the stack
+    fn on_enter(&mut self) {}
+    // called whenever the stage stops being the top of the stack
+    fn on_exit(&mut self) {}
+    // called when the window loses focus or the tab gets hidden
+    fn pause(&mut self) {}
+    fn is_paused(&self) -> bool {
+        false
+    }
+    fn hud(&self) -> Option<HudInfo> {
+        None
+    }
+    fn draw_debug(&self, _renderer: &Renderer, _flags: &DebugFlags) {}
+    // keys the stage uses itself, the debug overlay leaves them alone
+    fn claims_key(&self, _code: &str) -> bool {
+        false
+    }
+    fn update(&mut self, delta: f64) -> Transition;
     fn draw(&self, renderer: &Renderer);
 }
 
 pub struct GameStage {
     pub round: i64,
+    pub mode: GameMode,
     pub(crate) objs: Vec<Option<Box<dyn GameObject>>>,
     status: GameStatus,
-    auto_drive: Option<Rc<RefCell<AutoDrive>>>,
+    auto_drives: Vec<Rc<RefCell<AutoDrive>>>,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    pub config: Rc<GameConfig>,
+    key_bindings: KeyBindings,
+    elapsed: f64,
+    generation_elapsed: f64,
+    records: PlayerRecords,
+    // laps the player had finished by the last tick
+    player_laps: u32,
+    // the best lap of the player in a race, of any car in training
+    best_lap: Option<f64>,
+    new_record: bool,
 }
 
 impl GameStage {
-    pub async fn new(keyboard_state: Rc<RefCell<KeyboardState>>) -> Box<Self> {
+    pub async fn new(keyboard_state: Rc<RefCell<KeyboardState>>, config: Rc<GameConfig>, settings: Rc<RefCell<UserSettings>>, mode: GameMode, track_file: &TrackFile) -> Result<Box<Self>, GameError> {
+        let key_bindings = settings.borrow().key_bindings.clone();
         let mut objs: Vec<Option<Box<dyn GameObject>>> = vec![];
-        objs.push(Some(Box::new(GameStage::gen_track().await)));
-        let (auto_drive, mut cars) = Self::auto_drive_cars().await;
+        let track = Track::from_file(track_file).await?;
+        let (auto_drives, mut cars) = match mode {
+            GameMode::Race => Self::player_drive_car(keyboard_state.clone(), key_bindings.clone(), &config, &track.start_grid).await?,
+            GameMode::Training => Self::auto_drive_cars(&config, &track.start_grid).await?,
+        };
+        // pickups go before the cars so they are drawn underneath
+        let mut pickups: Vec<Option<Box<dyn GameObject>>> = vec![];
+        if mode == GameMode::Race && config.race.pickups {
+            for spot in track.pickups.iter() {
+                if let Some(center) = track.segments.get(spot.segment).and_then(|seg| seg.body.get_center()) {
+                    pickups.push(Some(Box::new(Pickup::new(spot.kind, &center))));
+                }
+            }
+        }
+        objs.push(Some(Box::new(track)));
+        objs.append(&mut pickups);
         objs.append(&mut cars);
-        Box::new(GameStage {
+        Ok(Box::new(GameStage {
             round: 1,
+            mode,
             status: GameStatus::Running,
             objs,
-            auto_drive,
-        })
+            auto_drives,
+            keyboard_state,
+            config,
+            key_bindings,
+            elapsed: 0.0,
+            generation_elapsed: 0.0,
+            records: storage::load::<PlayerRecords>(),
+            player_laps: 0,
+            best_lap: None,
+            new_record: false,
+        }))
+    }
+
+    fn car_spec<'a>(config: &'a GameConfig, name: &str) -> Result<&'a CarConfig, GameError> {
+        config.car(name).ok_or_else(|| GameError::Config(format!("unknown car `{}`", name)))
+    }
+
+    // tracks without a start grid fall back to the configured spawn point
+    fn grid_slot(config: &GameConfig, grid: &[SpawnConfig], slot: usize) -> SpawnConfig {
+        grid.get(slot).cloned().unwrap_or_else(|| config.spawn.clone())
     }
 
-    pub async fn player_drive_car(keyboard_state: Rc<RefCell<KeyboardState>>) -> (Option<Rc<RefCell<AutoDrive>>>, Vec<Option<Box<dyn GameObject>>>) {
+    fn auto_drive_for(auto_drives: &mut Vec<Rc<RefCell<AutoDrive>>>, config: &GameConfig, class: &str) -> Rc<RefCell<AutoDrive>> {
+        if let Some(auto_drive) = auto_drives.iter().find(|auto_drive| auto_drive.borrow().population() == class) {
+            return auto_drive.clone();
+        }
+        let auto_drive = Rc::new(RefCell::new(AutoDrive::new(config, class)));
+        auto_drives.push(auto_drive.clone());
+        auto_drive
+    }
+
+    // opponents race with whatever their class has learned so far
+    pub async fn player_drive_car(keyboard_state: Rc<RefCell<KeyboardState>>, key_bindings: KeyBindings, config: &GameConfig, grid: &[SpawnConfig]) -> Result<(Vec<Rc<RefCell<AutoDrive>>>, Vec<Option<Box<dyn GameObject>>>), GameError> {
         let mut cars: Vec<Option<Box<dyn GameObject>>> = vec![];
         let id = uuid::Uuid::new_v4();
-        let controller = Box::new(KeyController::new(id, keyboard_state));
-        cars.push(Some(Box::new(Car::new(id, 400.0, 80.0, CarType::No8, controller).await)));
-        (None, cars)
+        let controller = Box::new(KeyController::new(id, keyboard_state, key_bindings));
+        let spec = Self::car_spec(config, &config.race.player_car)?;
+        cars.push(Some(Box::new(Car::new(id, spec, &Self::grid_slot(config, grid, 0), controller).await?)));
+        let mut auto_drives = vec![];
+        for (index, class) in config.race.opponents.iter().enumerate() {
+            let auto_drive = Self::auto_drive_for(&mut auto_drives, config, class);
+            let id = uuid::Uuid::new_v4();
+            let controller = Box::new(AutoDriveController::new(id, auto_drive));
+            let spawn = Self::grid_slot(config, grid, index + 1);
+            cars.push(Some(Box::new(Car::new(id, Self::car_spec(config, class)?, &spawn, controller).await?)));
+        }
+        // the server has to know every class before the lights go out, or its cars would never move
+        for auto_drive in auto_drives.iter() {
+            let mut auto_drive = auto_drive.borrow_mut();
+            let count = config.race.opponents.iter().filter(|class| class.as_str() == auto_drive.population()).count() as u32;
+            auto_drive.load_network(config.training.num_cars.max(count)).await?;
+        }
+        Ok((auto_drives, cars))
     }
 
-    pub async fn auto_drive_cars() -> (Option<Rc<RefCell<AutoDrive>>>, Vec<Option<Box<dyn GameObject>>>) {
-        let auto_drive = Rc::new(RefCell::new(AutoDrive::new(0.3)));
+    pub async fn auto_drive_cars(config: &GameConfig, grid: &[SpawnConfig]) -> Result<(Vec<Rc<RefCell<AutoDrive>>>, Vec<Option<Box<dyn GameObject>>>), GameError> {
+        let mut auto_drives = vec![];
         let mut cars: Vec<Option<Box<dyn GameObject>>> = vec![];
-        for _ in 0..NUM_CARS {
-            let id = uuid::Uuid::new_v4();
-            let controller = Box::new(AutoDriveController::new(id, auto_drive.clone()));
-            cars.push(Some(Box::new(Car::new(id, 400.0, 80.0, CarType::No8, controller).await)));
+        // a generation all starts from pole so every driver is scored from the same spot
+        let spawn = Self::grid_slot(config, grid, 0);
+        for class in config.training.car_classes.iter() {
+            let spec = Self::car_spec(config, class)?;
+            let auto_drive = Self::auto_drive_for(&mut auto_drives, config, class);
+            for _ in 0..config.training.num_cars {
+                let id = uuid::Uuid::new_v4();
+                let controller = Box::new(AutoDriveController::new(id, auto_drive.clone()));
+                cars.push(Some(Box::new(Car::new(id, spec, &spawn, controller).await?)));
+            }
+        }
+        for auto_drive in auto_drives.iter() {
+            let mut auto_drive = auto_drive.borrow_mut();
+            auto_drive.new_network(config.training.num_cars).await?;
         }
-        auto_drive.borrow_mut().new_network().await;
-        (Some(auto_drive), cars)
+        Ok((auto_drives, cars))
     }
 
     pub fn find<T: GameObject>(&self) -> Vec<&T> {
@@ -85,58 +225,81 @@ impl GameStage {
         return found;
     }
 
-    fn reset_if_all_dead(&mut self) {
+    fn track_name(&self) -> String {
+        self.find::<Track>().first().map(|track| track.name.clone()).unwrap_or_default()
+    }
+
+    fn results(&self) -> RaceResults {
+        let best_score = self.find::<car::Car>().iter().fold(0.0, |best: f64, car| best.max(car.score.top_score));
+        RaceResults {
+            mode: self.mode,
+            track_name: self.track_name(),
+            rounds: self.round,
+            best_score,
+            elapsed: self.elapsed,
+            laps: self.player_laps,
+            best_lap: self.best_lap,
+            new_record: self.new_record,
+        }
+    }
+
+    // the player's laps go into the records, the race is over once all of them are driven
+    fn check_laps(&mut self) -> Transition {
+        // the player is the first car
+        let counted = if self.mode == GameMode::Race { 1 } else { usize::MAX };
+        let times: Vec<f64> = self.find::<car::Car>().iter().take(counted).filter_map(|car| car.laps.best_lap).collect();
+        for time in times {
+            self.best_lap = Some(self.best_lap.map_or(time, |best| best.min(time)));
+        }
+        if self.mode != GameMode::Race {
+            return Transition::None;
+        }
+        let (laps, last_lap) = match self.find::<car::Car>().first() {
+            Some(car) => (car.laps.laps, car.laps.last_lap()),
+            None => return Transition::None,
+        };
+        if laps > self.player_laps {
+            self.player_laps = laps;
+            let track = self.track_name();
+            if let Some(time) = last_lap {
+                if self.records.record_lap(&track, time) {
+                    crate::log_info!("new lap record on {}: {:.3}s", track, time);
+                    self.new_record = true;
+                    save_records(&self.records);
+                }
+            }
+        }
+        if self.config.race.laps > 0 && laps >= self.config.race.laps {
+            self.status = GameStatus::Over;
+            return Transition::Switch(StageRequest::Results(self.results()));
+        }
+        Transition::None
+    }
+
+    fn reset_if_all_dead(&mut self) -> Transition {
         let cars = self.find_mut::<car::Car>();
         let some_alive = cars.iter().any(|car| {
             car.status == CarStatus::Live
         });
         if !some_alive {
+            if self.mode == GameMode::Race {
+                self.status = GameStatus::Over;
+                return Transition::Switch(StageRequest::Results(self.results()));
+            }
             self.round += 1;
-            if let Some(ref auto_drive) = self.auto_drive {
+            self.generation_elapsed = 0.0;
+            for auto_drive in self.auto_drives.iter() {
                 auto_drive.borrow_mut().next_gen(self.find::<car::Car>());
             }
+            let grid = self.find::<Track>().first().map(|track| track.start_grid.clone()).unwrap_or_default();
+            let spawn = Self::grid_slot(&self.config, &grid, 0);
             for car in self.find_mut::<car::Car>() {
-                car.reset(&FVec::new(400.0, 80.0), 0.0);
+                car.reset(&FVec::new(spawn.x, spawn.y), spawn.rotate);
             }
         }
+        Transition::None
     }
 
-    pub async fn gen_track() -> Track {
-        return Track::new(0.0, 0.0, vec![
-            TrackSegmentDirection::UpRightRight,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::DownRightDown,
-            TrackSegmentDirection::Down,
-            TrackSegmentDirection::DownLeftLeft,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::DownLeftDown,
-            TrackSegmentDirection::DownRightRight,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::Right,
-            TrackSegmentDirection::DownRightDown,
-            TrackSegmentDirection::DownLeftLeft,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::Left,
-            TrackSegmentDirection::UpLeftUp,
-            TrackSegmentDirection::Up,
-            TrackSegmentDirection::Up,
-            TrackSegmentDirection::Up,
-        ]).await
-    }
 }
 
 impl Stage for GameStage {
@@ -151,8 +314,106 @@ impl Stage for GameStage {
         }
     }
 
-    fn update(&mut self, delta: f64) {
-        self.reset_if_all_dead();
+    fn draw_debug(&self, renderer: &Renderer, flags: &DebugFlags) {
+        let tracks = self.find::<Track>();
+        let track = tracks.first();
+        if flags.boundaries {
+            if let Some(track) = track {
+                for seg in track.segments.iter() {
+                    seg.debug(renderer);
+                }
+            }
+        }
+        for car in self.find::<car::Car>() {
+            if car.status != CarStatus::Live {
+                continue;
+            }
+            if flags.boundaries {
+                if let Some(seg) = track.and_then(|track| track.on_which_track_seg(&car.body)) {
+                    seg.debug_current(renderer);
+                }
+            }
+            if flags.sensors {
+                car.sensor.debug(renderer);
+            }
+            if flags.hitboxes {
+                car.body.debug_view(renderer);
+            }
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.status == GameStatus::Running {
+            self.status = GameStatus::Pause;
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.status == GameStatus::Pause
+    }
+
+    fn hud(&self) -> Option<HudInfo> {
+        let cars = self.find::<car::Car>();
+        match self.mode {
+            GameMode::Race => {
+                let car = cars.first()?;
+                Some(HudInfo::Race {
+                    speed: car.state.speed(),
+                    lap: car.laps.lap(),
+                    laps: self.config.race.laps,
+                    lap_time: car.laps.lap_time,
+                    lap_times: car.laps.lap_times.clone(),
+                    record: self.records.best_lap(&self.track_name()),
+                    damage: car.damage.amount,
+                    fuel: car.consumables.fuel_level(),
+                    tire_wear: car.consumables.tire_wear,
+                    pit_stop: car.consumables.pit_stop,
+                    effects: car.effects.active().iter().map(|(kind, remaining)| (kind.label().to_string(), *remaining)).collect(),
+                })
+            },
+            GameMode::Training => {
+                let scores: Vec<f64> = cars.iter().map(|car| car.score.score).collect();
+                let (best_score, average_score) = best_and_average(&scores);
+                Some(HudInfo::Training {
+                    generation: self.round,
+                    alive: cars.iter().filter(|car| car.status == CarStatus::Live).count(),
+                    total: cars.len(),
+                    best_score,
+                    average_score,
+                    generation_time: self.generation_elapsed,
+                })
+            },
+        }
+    }
+
+    fn update(&mut self, delta: f64) -> Transition {
+        let (toggle_pause, step, exit) = {
+            let keyboard_state = self.keyboard_state.borrow();
+            (keyboard_state.was_pressed(&self.key_bindings.pause), keyboard_state.was_pressed(&self.key_bindings.step), keyboard_state.was_pressed("Escape"))
+        };
+        if exit {
+            return match self.mode {
+                GameMode::Race => Transition::Pop,
+                GameMode::Training => Transition::Switch(StageRequest::Results(self.results())),
+            };
+        }
+        if toggle_pause {
+            self.status = match self.status {
+                GameStatus::Running => GameStatus::Pause,
+                GameStatus::Pause => GameStatus::Running,
+                GameStatus::Over => GameStatus::Over,
+            };
+        }
+        // while paused the step key advances exactly one tick
+        if self.status == GameStatus::Pause && !step {
+            return Transition::None;
+        }
+        self.elapsed += delta;
+        self.generation_elapsed += delta;
+        let transition = self.reset_if_all_dead();
+        if !matches!(transition, Transition::None) {
+            return transition;
+        }
         for i in 1..self.objs.len() {
             let mut obj = std::mem::replace(&mut self.objs[i], None);
             match obj {
@@ -163,11 +424,16 @@ impl Stage for GameStage {
             }
             self.objs[i] = obj;
         }
+        let transition = self.check_laps();
+        if !matches!(transition, Transition::None) {
+            return transition;
+        }
 
-        if let Some(ref auto_drive) = self.auto_drive {
+        for auto_drive in self.auto_drives.iter() {
             let mut auto_drive = auto_drive.borrow_mut();
             auto_drive.tick(delta);
-            auto_drive.evaluate(self.find::<car::Car>());
+            profile(Phase::Evaluate, || auto_drive.evaluate(self.find::<car::Car>()));
         }
+        Transition::None
     }
 }
@@ -0,0 +1,185 @@
+use super::*;
+use super::menu_stage::*;
+use super::results_stage::*;
+use super::error_stage::*;
+use super::track_editor::*;
+use crate::config::LaunchMode;
+
+enum StackOp {
+    Push,
+    Switch,
+}
+
+type PendingStages = Rc<RefCell<Vec<(StackOp, Box<dyn Stage>)>>>;
+
+pub struct StageManager {
+    stages: Vec<Box<dyn Stage>>,
+    pending: PendingStages,
+    loading: bool,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    mouse_state: Rc<RefCell<MouseState>>,
+    config: Rc<GameConfig>,
+    settings: Rc<RefCell<UserSettings>>,
+    tracks: Rc<Vec<TrackFile>>,
+    // the size of the canvas the stages draw on
+    size: FVec,
+}
+
+impl StageManager {
+    pub fn new(keyboard_state: Rc<RefCell<KeyboardState>>, mouse_state: Rc<RefCell<MouseState>>, config: Rc<GameConfig>, settings: Rc<RefCell<UserSettings>>, tracks: Rc<Vec<TrackFile>>, size: FVec) -> Self {
+        let mut manager = StageManager {
+            stages: vec![],
+            pending: Rc::new(RefCell::new(vec![])),
+            loading: false,
+            keyboard_state: keyboard_state.clone(),
+            mouse_state,
+            config,
+            settings,
+            tracks,
+            size,
+        };
+        manager.push(MainMenuStage::new(keyboard_state, size));
+        manager.launch();
+        manager
+    }
+
+    fn launch(&mut self) {
+        let launch = self.config.launch.clone();
+        let track_index = match launch.track {
+            Some(ref name) => match self.tracks.iter().position(|track| &track.name == name) {
+                Some(index) => index,
+                None => {
+                    let error = GameError::Config(format!("unknown track `{}`", name));
+                    crate::log_error!("{}", error);
+                    self.push(ErrorStage::new(self.keyboard_state.clone(), error, self.size));
+                    return;
+                },
+            },
+            None => 0,
+        };
+        match launch.mode {
+            LaunchMode::Menu => {},
+            LaunchMode::Race => self.load(StackOp::Push, StageRequest::Race(track_index)),
+            LaunchMode::Training => self.load(StackOp::Push, StageRequest::Training(track_index)),
+        }
+    }
+
+    pub fn current(&self) -> Option<&dyn Stage> {
+        self.stages.last().map(|stage| stage.as_ref())
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(stage) = self.stages.last_mut() {
+            stage.pause();
+        }
+    }
+
+    pub fn update(&mut self, delta: f64) {
+        self.apply_pending();
+        if self.loading {
+            return;
+        }
+        let transition = match self.stages.last_mut() {
+            Some(stage) => stage.update(delta),
+            None => Transition::None,
+        };
+        self.apply(transition);
+    }
+
+    pub fn draw(&self, renderer: &Renderer) {
+        if let Some(stage) = self.current() {
+            stage.draw(renderer);
+        }
+        if self.loading {
+            renderer.save();
+            renderer.fill_style("black");
+            renderer.font("18px sans-serif");
+            renderer.text_align(TextAlign::Right);
+            renderer.fill_text("Loading...", FVec::new(self.size.x - 20.0, 30.0));
+            renderer.restore();
+        }
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::None => {},
+            Transition::Pop => self.pop(),
+            Transition::Push(request) => self.load(StackOp::Push, request),
+            Transition::Switch(request) => self.load(StackOp::Switch, request),
+        }
+    }
+
+    // stages that need assets are built asynchronously, the result is picked up on a later tick
+    fn load(&mut self, op: StackOp, request: StageRequest) {
+        self.loading = true;
+        let pending = self.pending.clone();
+        let (keyboard_state, mouse_state) = (self.keyboard_state.clone(), self.mouse_state.clone());
+        let (config, settings, tracks, size) = (self.config.clone(), self.settings.clone(), self.tracks.clone(), self.size);
+        spawn_local(async move {
+            let stage = Self::build(keyboard_state, mouse_state, config, settings, tracks, size, request).await;
+            pending.borrow_mut().push((op, stage));
+        });
+    }
+
+    fn apply_pending(&mut self) {
+        let pending: Vec<(StackOp, Box<dyn Stage>)> = self.pending.borrow_mut().drain(..).collect();
+        for (op, stage) in pending {
+            match op {
+                StackOp::Push => self.push(stage),
+                StackOp::Switch => self.switch(stage),
+            }
+            self.loading = false;
+        }
+    }
+
+    async fn build(keyboard_state: Rc<RefCell<KeyboardState>>, mouse_state: Rc<RefCell<MouseState>>, config: Rc<GameConfig>, settings: Rc<RefCell<UserSettings>>, tracks: Rc<Vec<TrackFile>>, size: FVec, request: StageRequest) -> Box<dyn Stage> {
+        let track = |index: usize| tracks.get(index).cloned().ok_or_else(|| GameError::Config(format!("no track with index {}", index)));
+        let stage: Result<Box<dyn Stage>, GameError> = match request {
+            StageRequest::TrackSelect(mode) => Ok(TrackSelectStage::new(keyboard_state.clone(), settings, tracks.clone(), mode, size)),
+            StageRequest::Race(track_index) => match track(track_index) {
+                Ok(track) => GameStage::new(keyboard_state.clone(), config, settings, GameMode::Race, &track).await.map(|stage| stage as Box<dyn Stage>),
+                Err(err) => Err(err),
+            },
+            StageRequest::Training(track_index) => match track(track_index) {
+                Ok(track) => GameStage::new(keyboard_state.clone(), config, settings, GameMode::Training, &track).await.map(|stage| stage as Box<dyn Stage>),
+                Err(err) => Err(err),
+            },
+            StageRequest::Controls => Ok(ControlsStage::new(keyboard_state.clone(), settings, size)),
+            StageRequest::TrackEditor => TrackEditorStage::new(keyboard_state.clone(), mouse_state).await.map(|stage| stage as Box<dyn Stage>),
+            StageRequest::TestDrive(track) => GameStage::new(keyboard_state.clone(), config, settings, GameMode::Race, &track).await.map(|stage| stage as Box<dyn Stage>),
+            StageRequest::Results(results) => Ok(ResultsStage::new(keyboard_state.clone(), results, size)),
+        };
+        stage.unwrap_or_else(|err| {
+            crate::log_error!("failed to load stage: {}", err);
+            ErrorStage::new(keyboard_state, err, size)
+        })
+    }
+
+    fn push(&mut self, mut stage: Box<dyn Stage>) {
+        if let Some(top) = self.stages.last_mut() {
+            top.on_exit();
+        }
+        stage.on_enter();
+        self.stages.push(stage);
+    }
+
+    fn switch(&mut self, mut stage: Box<dyn Stage>) {
+        if let Some(mut top) = self.stages.pop() {
+            top.on_exit();
+        }
+        stage.on_enter();
+        self.stages.push(stage);
+    }
+
+    fn pop(&mut self) {
+        if self.stages.len() <= 1 {
+            return;
+        }
+        if let Some(mut top) = self.stages.pop() {
+            top.on_exit();
+        }
+        if let Some(top) = self.stages.last_mut() {
+            top.on_enter();
+        }
+    }
+}
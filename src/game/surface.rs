@@ -0,0 +1,47 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Surface {
+    Asphalt,
+    Kerb,
+    Grass,
+    Gravel,
+    Ice,
+}
+
+// `friction` scales the tire grip, `drag` is extra rolling resistance per unit of mass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceProperties {
+    pub friction: f64,
+    pub drag: f64,
+}
+
+impl Surface {
+    pub fn properties(&self) -> SurfaceProperties {
+        let (friction, drag) = match self {
+            Surface::Asphalt => (1.0, 0.0),
+            Surface::Kerb => (0.9, 0.05),
+            Surface::Grass => (0.6, 0.8),
+            Surface::Gravel => (0.5, 1.5),
+            Surface::Ice => (0.15, 0.0),
+        };
+        SurfaceProperties { friction, drag }
+    }
+
+    pub fn color(&self) -> &'static str {
+        match self {
+            Surface::Asphalt => "#555555",
+            Surface::Kerb => "#cc3333",
+            Surface::Grass => "#3f7f3a",
+            Surface::Gravel => "#b8a27a",
+            Surface::Ice => "#d8eef5",
+        }
+    }
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Surface::Asphalt
+    }
+}
@@ -1,13 +1,34 @@
 use web_sys::*;
-use std::f64::consts::PI;
+use std::collections::HashMap;
+use std::f64::consts::{PI, FRAC_1_SQRT_2};
 use crate::{engine::*, browser::load_image};
 use super::*;
 use uuid;
+use crate::error::{GameError, TrackIssue};
+use super::surface::Surface;
+use super::pickup::PickupSpot;
+use super::track_file::TrackFile;
+use super::spline::{ControlPoint, sample, spline_issues, span_turn};
+use crate::config::SpawnConfig;
 
-const TRACK_SEG_WIDTH: f64 = 150.0;
-const TRACK_SEG_HEIGHT: f64 = 150.0;
+pub(crate) const TRACK_SEG_WIDTH: f64 = 150.0;
+pub(crate) const TRACK_SEG_HEIGHT: f64 = 150.0;
+// unit vectors, so progress along a heading is the same distance on every kind of segment
+const UP: FVec = FVec::new(0.0, -1.0);
+const UP_RIGHT: FVec = FVec::new(FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+const RIGHT: FVec = FVec::new(1.0, 0.0);
+const DOWN_RIGHT: FVec = FVec::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2);
+const DOWN: FVec = FVec::new(0.0, 1.0);
+const DOWN_LEFT: FVec = FVec::new(-FRAC_1_SQRT_2, FRAC_1_SQRT_2);
+const LEFT: FVec = FVec::new(-1.0, 0.0);
+const UP_LEFT: FVec = FVec::new(-FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+const FINISH_LINE_WIDTH: f64 = 20.0;
+// the most a centerline pit lane may turn, in radians
+const MAX_PIT_LANE_TURN: f64 = PI / 18.0;
+// the strip of road along each boundary that counts as kerb
+const KERB_WIDTH: f64 = 10.0;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TrackSegmentDirection {
     Up,
     UpRightRight,
@@ -23,7 +44,90 @@ pub enum TrackSegmentDirection {
     UpLeftUp,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+impl TrackSegmentDirection {
+    pub const ALL: [TrackSegmentDirection; 12] = [
+        TrackSegmentDirection::Up,
+        TrackSegmentDirection::UpRightRight,
+        TrackSegmentDirection::UpRightUp,
+        TrackSegmentDirection::Right,
+        TrackSegmentDirection::DownRightRight,
+        TrackSegmentDirection::DownRightDown,
+        TrackSegmentDirection::Down,
+        TrackSegmentDirection::DownLeftLeft,
+        TrackSegmentDirection::DownLeftDown,
+        TrackSegmentDirection::Left,
+        TrackSegmentDirection::UpLeftLeft,
+        TrackSegmentDirection::UpLeftUp,
+    ];
+
+    // the tile names used by track files
+    pub fn name(&self) -> &'static str {
+        use TrackSegmentDirection::*;
+        match self {
+            Up => "up",
+            UpRightRight => "up_right_right",
+            UpRightUp => "up_right_up",
+            Right => "right",
+            DownRightRight => "down_right_right",
+            DownRightDown => "down_right_down",
+            Down => "down",
+            DownLeftLeft => "down_left_left",
+            DownLeftDown => "down_left_down",
+            Left => "left",
+            UpLeftLeft => "up_left_left",
+            UpLeftUp => "up_left_up",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|dir| dir.name() == name).cloned()
+    }
+
+    pub fn from_steps(entry: (i32, i32), exit: (i32, i32)) -> Option<Self> {
+        Self::ALL.iter().find(|dir| dir.entry_exit() == (entry, exit)).cloned()
+    }
+
+    // the way cars drive through the tile, corners point along their diagonal
+    pub fn heading(&self) -> FVec {
+        use TrackSegmentDirection::*;
+        match self {
+            Up => UP,
+            UpRightRight | UpRightUp => UP_RIGHT,
+            Right => RIGHT,
+            DownRightRight | DownRightDown => DOWN_RIGHT,
+            Down => DOWN,
+            DownLeftLeft | DownLeftDown => DOWN_LEFT,
+            Left => LEFT,
+            UpLeftLeft | UpLeftUp => UP_LEFT,
+        }
+    }
+
+    pub fn track_type(&self) -> TrackSegmentType {
+        Track::dir_to_type_and_offset(self, 0.0, 0.0).0
+    }
+
+    // the grid steps a car drives in when entering and leaving the tile, the last word of a corner is where it leaves to
+    pub fn entry_exit(&self) -> ((i32, i32), (i32, i32)) {
+        use TrackSegmentDirection::*;
+        let (up, right, down, left) = ((0, -1), (1, 0), (0, 1), (-1, 0));
+        match self {
+            Up => (up, up),
+            UpRightRight => (up, right),
+            UpRightUp => (right, up),
+            Right => (right, right),
+            DownRightRight => (down, right),
+            DownRightDown => (right, down),
+            Down => (down, down),
+            DownLeftLeft => (down, left),
+            DownLeftDown => (left, down),
+            Left => (left, left),
+            UpLeftLeft => (up, left),
+            UpLeftUp => (left, up),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum TrackSegmentType {
     UpDown,
     LeftRight,
@@ -32,47 +136,170 @@ pub enum TrackSegmentType {
     UpperLeft,
     UpperRight,
     FinishLine,
+    // straights where cars can stop for fuel and tires
+    PitLaneUpDown,
+    PitLaneLeftRight,
+    // a piece of a curved track, the road is whatever lies between its boundaries
+    Freeform,
+    FreeformPitLane,
+}
+
+impl TrackSegmentType {
+    pub fn is_pit_lane(&self) -> bool {
+        matches!(self, TrackSegmentType::PitLaneUpDown | TrackSegmentType::PitLaneLeftRight | TrackSegmentType::FreeformPitLane)
+    }
+
+    pub fn is_freeform(&self) -> bool {
+        matches!(self, TrackSegmentType::Freeform | TrackSegmentType::FreeformPitLane)
+    }
+
+    pub fn is_straight(&self) -> bool {
+        matches!(self, TrackSegmentType::UpDown | TrackSegmentType::LeftRight | TrackSegmentType::PitLaneUpDown | TrackSegmentType::PitLaneLeftRight)
+    }
+
+    // the grid steps leading out of the tile through its two open sides
+    pub fn openings(&self) -> Vec<(i32, i32)> {
+        use TrackSegmentType::*;
+        let (up, right, down, left) = ((0, -1), (1, 0), (0, 1), (-1, 0));
+        match self {
+            UpDown | PitLaneUpDown => vec![up, down],
+            LeftRight | PitLaneLeftRight => vec![left, right],
+            LowerLeft => vec![up, right],
+            LowerRight => vec![up, left],
+            UpperLeft => vec![down, right],
+            UpperRight => vec![down, left],
+            FinishLine | Freeform | FreeformPitLane => vec![],
+        }
+    }
+
+    // the same tile turned a quarter clockwise
+    pub fn rotated(&self) -> TrackSegmentType {
+        use TrackSegmentType::*;
+        match self {
+            UpDown => LeftRight,
+            LeftRight => UpDown,
+            PitLaneUpDown => PitLaneLeftRight,
+            PitLaneLeftRight => PitLaneUpDown,
+            LowerLeft => UpperLeft,
+            UpperLeft => UpperRight,
+            UpperRight => LowerRight,
+            LowerRight => LowerLeft,
+            FinishLine | Freeform | FreeformPitLane => *self,
+        }
+    }
+
+    // freeform pieces are painted instead
+    pub fn image_src(&self) -> Option<&'static str> {
+        use TrackSegmentType::*;
+        match self {
+            UpDown | PitLaneUpDown => Some("track_up.png"),
+            LeftRight | PitLaneLeftRight => Some("track_left.png"),
+            LowerLeft => Some("track_lower_left.png"),
+            LowerRight => Some("track_lower_right.png"),
+            UpperLeft => Some("track_upper_left.png"),
+            UpperRight => Some("track_upper_right.png"),
+            FinishLine => Some("finish_line.png"),
+            Freeform | FreeformPitLane => None,
+        }
+    }
+
+    fn pit_lane(&self) -> Option<TrackSegmentType> {
+        match self {
+            TrackSegmentType::UpDown => Some(TrackSegmentType::PitLaneUpDown),
+            TrackSegmentType::LeftRight => Some(TrackSegmentType::PitLaneLeftRight),
+            TrackSegmentType::Freeform => Some(TrackSegmentType::FreeformPitLane),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct TrackSegment {
     pub id: uuid::Uuid,
     pub track_type: TrackSegmentType,
-    pub direction: TrackSegmentDirection,
+    // the way cars drive through the segment
+    pub heading: FVec,
+    // the line across the road cars drive in over
+    pub entry: Line,
     pub finish_line: Option<Line>,
     pub boundaries: Vec<Line>,
     pub body: BoundingBox,
-    image: HtmlImageElement,
-    debug: bool,
+    // `surface` is the road itself, `verge` the part of the tile outside the boundaries
+    pub surface: Surface,
+    pub verge: Surface,
+    image: Option<HtmlImageElement>,
+    // the dash painted down the middle of a freeform road
+    center_mark: Option<Line>,
 }
 
 impl TrackSegment {
-    pub async fn new(x: f64, y: f64, track_type: TrackSegmentType, direction: TrackSegmentDirection) -> Self {
-        let (image_src, lines) = match track_type {
-            TrackSegmentType::UpDown => ("track_up.png", vec![Line::new(x, y, x, y + 150.0), Line::new(x + 150.0, y, x + 150.0, y + 150.0)]),
-            TrackSegmentType::LeftRight => ("track_left.png", vec![Line::new(x, y, x + 150.0, y), Line::new(x, y + 150.0, x + 150.0, y + 150.0)]),
-            TrackSegmentType::LowerLeft => ("track_lower_left.png", Self::arc_lines(0.0, 150.0, x + 150.0, y, PI / 2.0, 8)),
-            TrackSegmentType::LowerRight => ("track_lower_right.png", Self::arc_lines(150.0, -0.0, x, y, PI / 2.0, 8)),
-            TrackSegmentType::UpperLeft => ("track_upper_left.png", Self::arc_lines(-150.0, 0.0, x + 150.0, y + 150.0, PI / 2.0, 8)),
-            TrackSegmentType::UpperRight => ("track_upper_right.png", Self::arc_lines(0.0, -150.0, x, y + 150.0, PI / 2.0, 8)),
-            TrackSegmentType::FinishLine => ("finish_line.png", vec![]),
-        };
-        let image = load_image(image_src).await.unwrap();
-        return TrackSegment {
+    // a tile whose top left corner is at `x`, `y`, drawn once `load_image` is done
+    pub fn tile(x: f64, y: f64, track_type: TrackSegmentType, direction: TrackSegmentDirection) -> Self {
+        TrackSegment {
             id: uuid::Uuid::new_v4(),
-            image,
+            image: None,
+            center_mark: None,
             track_type,
-            direction,
+            heading: direction.heading(),
+            entry: Self::entry_side(x, y, direction.entry_exit().0),
             finish_line: None,
-            boundaries: lines,
+            boundaries: Self::boundary_lines(track_type, x, y),
+            surface: Surface::Asphalt,
+            verge: Surface::Grass,
             body: BoundingBox::new_with_origin(&Rect { x, y, w: TRACK_SEG_WIDTH, h: TRACK_SEG_HEIGHT }, FVec { x: 0.0, y: 0.0}),
-            debug: false,
         }
     }
 
-    pub fn set_debug(mut self, debug: bool) -> Self {
-        self.debug = debug;
-        return self;
+    pub async fn load_image(&mut self) -> Result<(), GameError> {
+        if let Some(src) = self.track_type.image_src() {
+            self.image = Some(load_image(src).await?);
+        }
+        Ok(())
+    }
+
+    // a piece of road between two points on each edge, given in driving order
+    pub fn freeform(left: [FVec; 2], right: [FVec; 2], surface: Surface, center_mark: Option<Line>) -> Self {
+        let center = |a: &FVec, b: &FVec| FVec::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+        let (from, to) = (center(&left[0], &right[0]), center(&left[1], &right[1]));
+        let length = from.distance(&to);
+        let heading = if length > 0.0 { FVec::new((to.x - from.x) / length, (to.y - from.y) / length) } else { FVec::default() };
+        TrackSegment {
+            id: uuid::Uuid::new_v4(),
+            image: None,
+            center_mark,
+            track_type: TrackSegmentType::Freeform,
+            heading,
+            entry: Line { start: left[0], end: right[0] },
+            finish_line: None,
+            boundaries: vec![Line { start: left[0], end: left[1] }, Line { start: right[0], end: right[1] }],
+            surface,
+            verge: Surface::Grass,
+            body: BoundingBox::from_quad([left[0], left[1], right[1], right[0]]),
+        }
+    }
+
+    // the side of the tile at `x`, `y` that cars moving by `step` drive in through
+    fn entry_side(x: f64, y: f64, step: (i32, i32)) -> Line {
+        let (w, h) = (TRACK_SEG_WIDTH, TRACK_SEG_HEIGHT);
+        match step {
+            (1, 0) => Line::new(x, y, x, y + h),
+            (-1, 0) => Line::new(x + w, y, x + w, y + h),
+            (0, 1) => Line::new(x, y, x + w, y),
+            _ => Line::new(x, y + h, x + w, y + h),
+        }
+    }
+
+    // the walls of a tile whose top left corner is at `x`, `y`
+    pub fn boundary_lines(track_type: TrackSegmentType, x: f64, y: f64) -> Vec<Line> {
+        match track_type {
+            TrackSegmentType::UpDown | TrackSegmentType::PitLaneUpDown => vec![Line::new(x, y, x, y + 150.0), Line::new(x + 150.0, y, x + 150.0, y + 150.0)],
+            TrackSegmentType::LeftRight | TrackSegmentType::PitLaneLeftRight => vec![Line::new(x, y, x + 150.0, y), Line::new(x, y + 150.0, x + 150.0, y + 150.0)],
+            TrackSegmentType::LowerLeft => Self::arc_lines(0.0, 150.0, x + 150.0, y, PI / 2.0, 8),
+            TrackSegmentType::LowerRight => Self::arc_lines(150.0, -0.0, x, y, PI / 2.0, 8),
+            TrackSegmentType::UpperLeft => Self::arc_lines(-150.0, 0.0, x + 150.0, y + 150.0, PI / 2.0, 8),
+            TrackSegmentType::UpperRight => Self::arc_lines(0.0, -150.0, x, y + 150.0, PI / 2.0, 8),
+            TrackSegmentType::FinishLine | TrackSegmentType::Freeform | TrackSegmentType::FreeformPitLane => vec![],
+        }
     }
 
     fn arc_lines(start_x: f64, start_y: f64, center_x: f64, center_y: f64, total_radis: f64, num_seg: i32) -> Vec<Line> {
@@ -91,7 +318,7 @@ impl TrackSegment {
         return lines;
     }
 
-    pub fn collide(&self, other_body: &BoundingBox) -> Option<FVec> {
+    pub fn collide(&self, other_body: &BoundingBox) -> Option<Contact> {
         let (_, lines) = other_body.get_coordinates();
         if let(Some(center), Some(other_center)) = ( self.body.get_center(), other_body.get_center()) {
             if center.distance(&other_center) > 500.0 {
@@ -102,7 +329,7 @@ impl TrackSegment {
             for line in lines {
                 for collide_line in &self.boundaries {
                     if let Some(point) = line.intersect(collide_line) {
-                        return Some(point);
+                        return Some(Contact { point, normal: collide_line.normal() });
                     }
                 }
             }
@@ -110,30 +337,269 @@ impl TrackSegment {
         None
     }
 
-    fn debug(&self, renderer: &Renderer) {
+    pub fn surface_at(&self, point: &FVec) -> Option<Surface> {
+        let distance = if self.track_type.is_freeform() {
+            // a freeform piece has no verge, everything off the road is off the track
+            if !self.body.contains(point) {
+                return None;
+            }
+            self.boundaries.iter().map(|line| line.distance_to_point(point)).fold(f64::INFINITY, f64::min)
+        } else {
+            edge_distance(self.track_type, &self.body.rect, point)?
+        };
+        if distance < 0.0 {
+            Some(self.verge)
+        } else if distance < KERB_WIDTH {
+            Some(Surface::Kerb)
+        } else {
+            Some(self.surface)
+        }
+    }
+
+    pub fn debug_current(&self, renderer: &Renderer) {
+        if let (Some(points), _) = self.body.get_coordinates() {
+            renderer.save();
+            renderer.stroke_style("yellow");
+            renderer.line_width(3.0);
+            renderer.polygon(points, false);
+            renderer.fill_style("yellow");
+            renderer.font("12px monospace");
+            renderer.fill_text(&format!("{:?} {:.0}°", self.track_type, self.heading.y.atan2(self.heading.x).to_degrees()), FVec::new(self.body.rect.x + 6.0, self.body.rect.y + 16.0));
+            renderer.restore();
+        }
+    }
+
+    pub fn debug(&self, renderer: &Renderer) {
+        renderer.save();
+        renderer.stroke_style("orange");
+        renderer.line_width(2.0);
         for line in &self.boundaries {
             renderer.line(line);
         }
+        renderer.restore();
+    }
+}
+
+// walks the layout on the grid: every tile has to continue from the one before, no two tiles may share a cell
+// and the last tile has to lead back into the first the way the first is entered
+pub fn layout_issues(directions: &[TrackSegmentDirection]) -> Vec<TrackIssue> {
+    let mut issues = vec![];
+    let (first, last) = match (directions.first(), directions.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return vec![TrackIssue::Empty],
+    };
+    let mut cells: HashMap<(i32, i32), usize> = HashMap::new();
+    let mut cell = (0, 0);
+    for (segment, dir) in directions.iter().enumerate() {
+        if let Some(other) = cells.insert(cell, segment) {
+            issues.push(TrackIssue::Overlap { segment, other });
+        }
+        if segment > 0 && directions[segment - 1].entry_exit().1 != dir.entry_exit().0 {
+            issues.push(TrackIssue::Gap { segment });
+        }
+        let exit = dir.entry_exit().1;
+        cell = (cell.0 + exit.0, cell.1 + exit.1);
+    }
+    if cell != (0, 0) {
+        issues.push(TrackIssue::NotClosed);
+    } else if last.entry_exit().1 != first.entry_exit().0 {
+        issues.push(TrackIssue::ClosingGap);
+    }
+    issues
+}
+
+// the pit lane has to be a straight tile, a segment missing from the layout is not one
+pub fn pit_lane_issues(directions: &[TrackSegmentDirection], pit_lane: Option<usize>) -> Vec<TrackIssue> {
+    match pit_lane {
+        Some(segment) if !directions.get(segment).map_or(false, |dir| dir.track_type().is_straight()) => vec![TrackIssue::PitLaneNotStraight { segment }],
+        _ => vec![],
+    }
+}
+
+// on a centerline track the pit lane is the span after a control point, it may barely turn
+pub fn spline_pit_lane_issues(points: &[ControlPoint], resolution: f64, pit_lane: Option<usize>) -> Vec<TrackIssue> {
+    match pit_lane {
+        Some(segment) if segment >= points.len() || span_turn(points, segment, resolution) > MAX_PIT_LANE_TURN => vec![TrackIssue::PitLaneNotStraight { segment }],
+        _ => vec![],
+    }
+}
+
+// how far `point` is inside the road of a tile, negative outside the boundaries and None off the tile
+pub fn edge_distance(track_type: TrackSegmentType, rect: &Rect, point: &FVec) -> Option<f64> {
+    use TrackSegmentType::*;
+    let inside = point.x >= rect.x && point.x <= rect.x + rect.w && point.y >= rect.y && point.y <= rect.y + rect.h;
+    if !inside {
+        return None;
+    }
+    // corner tiles are a quarter of a disc around one tile corner
+    let corner = |x: f64, y: f64| Some(rect.w - point.distance(&FVec::new(x, y)));
+    match track_type {
+        UpDown | PitLaneUpDown => Some((point.x - rect.x).min(rect.x + rect.w - point.x)),
+        LeftRight | PitLaneLeftRight => Some((point.y - rect.y).min(rect.y + rect.h - point.y)),
+        LowerLeft => corner(rect.x + rect.w, rect.y),
+        LowerRight => corner(rect.x, rect.y),
+        UpperLeft => corner(rect.x + rect.w, rect.y + rect.h),
+        UpperRight => corner(rect.x, rect.y + rect.h),
+        FinishLine => Some(f64::INFINITY),
+        // measured against their boundaries by the segment
+        Freeform | FreeformPitLane => None,
+    }
+}
+
+// a line across the road that counts when crossed in the direction of `heading`
+#[derive(Debug, Clone, Copy)]
+pub struct Gate {
+    pub line: Line,
+    pub heading: FVec,
+}
+
+impl Gate {
+    pub fn crossed_by(&self, from: &FVec, to: &FVec) -> bool {
+        (*to - *from).dot(&self.heading) > 0.0 && self.line.intersect(&Line { start: *from, end: *to }).is_some()
     }
 }
 
 pub struct Track {
-    pub segments: Vec<TrackSegment>
+    pub name: String,
+    pub segments: Vec<TrackSegment>,
+    // everything away from the segments
+    pub off_track: Surface,
+    pub pickups: Vec<PickupSpot>,
+    // segment indices a lap has to pass in order
+    pub checkpoints: Vec<usize>,
+    // the checkpoints in order and the finish line last, a lap is done once all of them are crossed
+    pub gates: Vec<Gate>,
+    pub start_grid: Vec<SpawnConfig>,
+    finish_image: Option<HtmlImageElement>,
 }
 
 impl Track {
-    pub async fn new (start_x: f64, start_y: f64, dir_and_types: Vec<TrackSegmentDirection>) -> Self {
+    pub async fn from_file(file: &TrackFile) -> Result<Self, GameError> {
+        let directions = file.validate()?;
+        // checkpoints and pickups in a file count tiles or control points,
+        // `first_segment` maps them to the segments built for them
+        let (track, first_segment) = if file.is_tiled() {
+            let track = Self::new(file.origin.x, file.origin.y, directions, file.pit_lane).await;
+            (track, (0..file.segments.len()).collect())
+        } else {
+            Self::from_spline(&file.centerline, file.resolution(), file.pit_lane)
+        };
+        let mut track = track.map_err(|err| match err {
+            GameError::InvalidTrack { issues, .. } => GameError::InvalidTrack { name: file.name.clone(), issues },
+            err => err,
+        })?;
+        track.name = file.name.clone();
+        track.set_off_track(file.off_track);
+        for (seg, spec) in track.segments.iter_mut().zip(file.segments.iter()) {
+            seg.surface = spec.surface();
+        }
+        track.pickups = file.pickups.iter()
+            .map(|pickup| PickupSpot { segment: first_segment[pickup.segment], ..pickup.clone() })
+            .collect();
+        track.checkpoints = file.checkpoints.iter().map(|index| first_segment[*index]).collect();
+        track.start_grid = file.start_grid.clone();
+        track.place_gates();
+        if let Some(src) = TrackSegmentType::FinishLine.image_src() {
+            track.finish_image = Some(load_image(src).await?);
+        }
+        Ok(track)
+    }
+
+    // one freeform segment per sampled piece, the returned list holds the first segment of every control point
+    pub fn from_spline(points: &[ControlPoint], resolution: f64, pit_lane: Option<usize>) -> (Result<Self, GameError>, Vec<usize>) {
+        let mut issues = spline_issues(points, resolution);
+        if issues.is_empty() {
+            issues.extend(spline_pit_lane_issues(points, resolution, pit_lane));
+        }
+        if !issues.is_empty() {
+            return (Err(GameError::InvalidTrack { name: String::new(), issues }), vec![]);
+        }
+        let samples = sample(points, resolution);
+        let mut first_segment = vec![0; points.len()];
+        let mut segments = vec![];
+        for (index, current) in samples.iter().enumerate() {
+            let next = &samples[(index + 1) % samples.len()];
+            if index == 0 || samples[index - 1].span != current.span {
+                first_segment[current.span] = index;
+            }
+            let center_mark = if index % 2 == 0 { Some(Line { start: current.center, end: next.center }) } else { None };
+            let mut seg = TrackSegment::freeform([current.left, next.left], [current.right, next.right], points[current.span].surface, center_mark);
+            if pit_lane == Some(current.span) {
+                seg.track_type = TrackSegmentType::FreeformPitLane;
+            }
+            segments.push(seg);
+        }
+        (Ok(Track { name: String::new(), segments, off_track: Surface::Grass, pickups: vec![], checkpoints: vec![], gates: vec![], start_grid: vec![], finish_image: None }), first_segment)
+    }
+
+    pub async fn new (start_x: f64, start_y: f64, dir_and_types: Vec<TrackSegmentDirection>, pit_lane: Option<usize>) -> Result<Self, GameError> {
+        let mut track = Self::from_tiles(start_x, start_y, dir_and_types, pit_lane)?;
+        for seg in track.segments.iter_mut() {
+            seg.load_image().await?;
+        }
+        Ok(track)
+    }
+
+    // the tiles laid from `start_x`, `start_y` without their images
+    pub fn from_tiles(start_x: f64, start_y: f64, dir_and_types: Vec<TrackSegmentDirection>, pit_lane: Option<usize>) -> Result<Self, GameError> {
+        let mut issues = layout_issues(&dir_and_types);
+        issues.extend(pit_lane_issues(&dir_and_types, pit_lane));
+        if !issues.is_empty() {
+            return Err(GameError::InvalidTrack { name: String::new(), issues });
+        }
         let mut current_x = start_x;
         let mut current_y = start_y;
         let mut segments = vec![];
-        for dir in dir_and_types.into_iter() {
-            let (track_type, x, y) = Self::dir_to_type_and_offset(&dir, current_x, current_y);
-            let seg = TrackSegment::new(current_x, current_y, track_type, dir).await;
-            segments.push(seg);
+        for (index, dir) in dir_and_types.into_iter().enumerate() {
+            let (mut track_type, x, y) = Self::dir_to_type_and_offset(&dir, current_x, current_y);
+            if pit_lane == Some(index) {
+                track_type = track_type.pit_lane().unwrap_or(track_type);
+            }
+            segments.push(TrackSegment::tile(current_x, current_y, track_type, dir));
             current_x = x;
             current_y = y;
         }
-        Track { segments }
+        Ok(Track { name: String::new(), segments, off_track: Surface::Grass, pickups: vec![], checkpoints: vec![], gates: vec![], start_grid: vec![], finish_image: None })
+    }
+
+    // the finish line is where the first segment begins, most of the start grid is behind it.
+    // a track without checkpoints gets one halfway round so crossing the line right after the start is not a lap
+    fn place_gates(&mut self) {
+        let mut checkpoints = self.checkpoints.clone();
+        if checkpoints.is_empty() && self.segments.len() > 1 {
+            checkpoints.push(self.segments.len() / 2);
+        }
+        checkpoints.push(0);
+        self.gates = checkpoints.iter()
+            .filter_map(|index| self.segments.get(*index))
+            .map(|seg| Gate { line: seg.entry, heading: seg.heading })
+            .collect();
+        if let Some(first) = self.segments.first_mut() {
+            first.finish_line = Some(first.entry);
+        }
+    }
+
+    pub fn set_off_track(&mut self, surface: Surface) {
+        self.off_track = surface;
+        for seg in self.segments.iter_mut() {
+            seg.verge = surface;
+        }
+    }
+
+    pub fn surface_at(&self, point: &FVec) -> Surface {
+        self.segments.iter()
+            .find_map(|seg| seg.surface_at(point))
+            .unwrap_or(self.off_track)
+    }
+
+    fn bounds(&self) -> Rect {
+        let (mut min, mut max) = (FVec::new(f64::MAX, f64::MAX), FVec::new(f64::MIN, f64::MIN));
+        for seg in self.segments.iter() {
+            let rect = &seg.body.rect;
+            min = FVec::new(min.x.min(rect.x), min.y.min(rect.y));
+            max = FVec::new(max.x.max(rect.x + rect.w), max.y.max(rect.y + rect.h));
+        }
+        Rect { x: min.x, y: min.y, w: max.x - min.x, h: max.y - min.y }
     }
 
     fn dir_to_type_and_offset(dir: &TrackSegmentDirection, current_x: f64, current_y: f64) -> (TrackSegmentType, f64, f64){
@@ -142,7 +608,7 @@ impl Track {
         match *dir {
             Up => (UpDown, current_x, current_y - TRACK_SEG_WIDTH),
             UpRightRight => (UpperLeft, current_x + TRACK_SEG_HEIGHT, current_y),
-            UpRightUp => (UpperLeft, current_x, current_y - TRACK_SEG_HEIGHT),
+            UpRightUp => (LowerRight, current_x, current_y - TRACK_SEG_HEIGHT),
             Right => (LeftRight, current_x + TRACK_SEG_WIDTH, current_y),
             DownRightRight  => (LowerLeft, current_x + TRACK_SEG_WIDTH, current_y),
             DownRightDown  => (UpperRight, current_x, current_y + TRACK_SEG_WIDTH),
@@ -158,6 +624,10 @@ impl Track {
 
 impl TrackSegment {
     fn draw(&self, renderer: &Renderer) {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return self.draw_freeform(renderer),
+        };
         let x = self.body.rect.x;
         let y = self.body.rect.y;
         let w = self.body.rect.w;
@@ -168,16 +638,46 @@ impl TrackSegment {
         renderer.translate(&FVec { x: x + origin_x, y: y + origin_y });
         renderer.rotate(self.body.rotate);
         renderer.translate(&FVec { x: -x - origin_x, y: -y - origin_y });
-        renderer.draw_image_with_dest(&self.image, &Rect { x: x - origin_x, y: y - origin_y, w, h });
+        renderer.draw_image_with_dest(image, &Rect { x: x - origin_x, y: y - origin_y, w, h });
+        if self.track_type.is_pit_lane() {
+            renderer.fill(&Rect { x: x - origin_x, y: y - origin_y, w, h }, "rgba(255, 220, 0, 0.25)");
+        }
+        renderer.restore();
+    }
+
+    fn draw_freeform(&self, renderer: &Renderer) {
+        let points = match &self.body.points {
+            Some(points) => points,
+            None => return,
+        };
+        renderer.save();
+        renderer.fill_style(self.surface.color());
+        // stroke with the fill so neighbouring pieces leave no hairline gap between them
+        renderer.stroke_style(self.surface.color());
+        renderer.polygon(points, true);
+        renderer.polygon(points, false);
+        if self.track_type.is_pit_lane() {
+            renderer.fill_style("rgba(255, 220, 0, 0.25)");
+            renderer.polygon(points, true);
+        }
+        renderer.stroke_style("white");
+        renderer.line_width(3.0);
+        for line in &self.boundaries {
+            renderer.line(line);
+        }
+        if let Some(mark) = &self.center_mark {
+            renderer.line_width(2.0);
+            renderer.line(mark);
+        }
         renderer.restore();
     }
 }
 
 impl Track {
-    pub fn on_collide(&self, car: &car::Car) -> Option<(FVec, TrackSegmentType)> {
+    pub fn on_collide(&self, car: &car::Car) -> Option<(Contact, TrackSegmentType)> {
         for track_seg in self.segments.iter() {
-            if let Some(point) = track_seg.collide(&car.body) {
-                return Some((point, track_seg.track_type));
+            if let Some(contact) = track_seg.collide(&car.body) {
+                return Some((contact, track_seg.track_type));
             }
         }
         None
@@ -205,14 +705,139 @@ impl GameObject for Track {
     }
 
     fn draw(&self, renderer: &Renderer) {
+        if !self.segments.is_empty() {
+            let bounds = self.bounds();
+            let margin = TRACK_SEG_WIDTH * 4.0;
+            renderer.fill(&Rect { x: bounds.x - margin, y: bounds.y - margin, w: bounds.w + margin * 2.0, h: bounds.h + margin * 2.0 }, self.off_track.color());
+        }
         for track_seg in self.segments.iter() {
             track_seg.draw(renderer);
-            if track_seg.debug {
-                track_seg.debug(renderer);
-            }
+        }
+        let finish = self.segments.first().and_then(|seg| seg.finish_line);
+        if let (Some(line), Some(image)) = (finish, &self.finish_image) {
+            // the checkered strip is drawn standing up, turn it to lie along the line
+            let center = FVec::new((line.start.x + line.end.x) / 2.0, (line.start.y + line.end.y) / 2.0);
+            let (length, angle) = (line.start.distance(&line.end), (line.end.y - line.start.y).atan2(line.end.x - line.start.x));
+            renderer.save();
+            renderer.translate(&center);
+            renderer.rotate(angle - PI / 2.0);
+            renderer.draw_image_with_dest(image, &Rect { x: -FINISH_LINE_WIDTH / 2.0, y: -length / 2.0, w: FINISH_LINE_WIDTH, h: length });
+            renderer.restore();
         }
     }
 
     fn update(&mut self, stage: &mut GameStage, delta: f64) {
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile() -> Rect {
+        Rect { x: 0.0, y: 0.0, w: TRACK_SEG_WIDTH, h: TRACK_SEG_HEIGHT }
+    }
+
+    #[test]
+    fn test_straight_edge_distance() {
+        assert_eq!(Some(75.0), edge_distance(TrackSegmentType::UpDown, &tile(), &FVec::new(75.0, 10.0)));
+        assert_eq!(Some(5.0), edge_distance(TrackSegmentType::UpDown, &tile(), &FVec::new(145.0, 10.0)));
+        assert_eq!(Some(20.0), edge_distance(TrackSegmentType::LeftRight, &tile(), &FVec::new(75.0, 20.0)));
+        assert_eq!(None, edge_distance(TrackSegmentType::UpDown, &tile(), &FVec::new(160.0, 10.0)), "off the tile");
+    }
+
+    fn layout(names: &[&str]) -> Vec<TrackIssue> {
+        let directions: Vec<TrackSegmentDirection> = names.iter().map(|name| TrackSegmentDirection::from_name(name).unwrap()).collect();
+        layout_issues(&directions)
+    }
+
+    #[test]
+    fn test_closed_layout() {
+        assert!(layout(&["right", "down_right_down", "down_left_left", "left", "up_left_up", "up_right_right"]).is_empty());
+        assert_eq!(vec![TrackIssue::Empty], layout(&[]));
+    }
+
+    #[test]
+    fn test_open_layout() {
+        assert_eq!(vec![TrackIssue::NotClosed], layout(&["right", "down_right_down", "down_left_left", "left", "up_left_up"]));
+        assert_eq!(vec![TrackIssue::Gap { segment: 2 }, TrackIssue::NotClosed], layout(&["right", "right", "up"]));
+    }
+
+    #[test]
+    fn test_closing_heading() {
+        // back on the start cell, but driving up into a tile that is entered going right
+        let issues = layout(&["right", "down_right_down", "down_left_left", "up_left_up"]);
+        assert_eq!(vec![TrackIssue::ClosingGap], issues);
+    }
+
+    #[test]
+    fn test_overlapping_layout() {
+        let issues = layout(&["right", "down_right_down", "down_left_left", "up_left_up", "up_right_right", "right", "down_right_down", "down_left_left", "left", "up_left_up", "up_right_right"]);
+        assert!(issues.contains(&TrackIssue::Overlap { segment: 4, other: 0 }), "{:?}", issues);
+    }
+
+    #[test]
+    fn test_tile_openings_match_directions() {
+        for dir in TrackSegmentDirection::ALL.iter() {
+            let (entry, exit) = dir.entry_exit();
+            let openings = dir.track_type().openings();
+            assert!(openings.contains(&(-entry.0, -entry.1)) && openings.contains(&exit), "{:?}", dir);
+            assert_eq!(Some(*dir), TrackSegmentDirection::from_steps(entry, exit));
+        }
+        let mut track_type = TrackSegmentType::LowerLeft;
+        for _ in 0..4 {
+            track_type = track_type.rotated();
+        }
+        assert_eq!(TrackSegmentType::LowerLeft, track_type, "four quarter turns are a full turn");
+    }
+
+    #[test]
+    fn test_spline_segments() {
+        let point = |x: f64, y: f64| ControlPoint { x, y, width: 100.0, surface: Surface::Asphalt };
+        let points = vec![point(0.0, 0.0), point(400.0, 0.0), point(400.0, 400.0), point(0.0, 400.0)];
+        let (track, first_segment) = Track::from_spline(&points, 25.0, None);
+        let track = track.unwrap();
+        assert_eq!(0, first_segment[0]);
+        assert!(first_segment.windows(2).all(|pair| pair[0] < pair[1]));
+        let seg = &track.segments[first_segment[1]];
+        assert!((seg.heading.x.hypot(seg.heading.y) - 1.0).abs() < 1e-9);
+        let middle = &track.segments[(first_segment[1] + first_segment[2]) / 2];
+        assert!(middle.heading.y > 0.9, "the right hand side of the square runs down");
+        // the curve bulges out between the corners, halfway along the top its center is at y = -50
+        assert_eq!(Surface::Asphalt, track.surface_at(&FVec::new(210.0, -50.0)));
+        assert_eq!(Surface::Kerb, track.surface_at(&FVec::new(210.0, -5.0)));
+        assert_eq!(Surface::Grass, track.surface_at(&FVec::new(210.0, 10.0)));
+        assert_eq!(Surface::Grass, track.surface_at(&FVec::new(200.0, 200.0)));
+        assert!(matches!(Track::from_spline(&points, 25.0, Some(1)).0, Err(GameError::InvalidTrack { .. })), "every side of the square curves");
+        // the points either side of the second span are in line with it, so it can be the pit lane
+        let points = vec![point(0.0, 0.0), point(200.0, 0.0), point(400.0, 0.0), point(600.0, 0.0), point(600.0, 400.0), point(0.0, 400.0)];
+        let (track, first_segment) = Track::from_spline(&points, 25.0, Some(1));
+        assert_eq!(TrackSegmentType::FreeformPitLane, track.unwrap().segments[first_segment[1]].track_type);
+    }
+
+    #[test]
+    fn test_gates() {
+        let point = |x: f64, y: f64| ControlPoint { x, y, width: 100.0, surface: Surface::Asphalt };
+        let points = vec![point(0.0, 0.0), point(400.0, 0.0), point(400.0, 400.0), point(0.0, 400.0)];
+        let mut track = Track::from_spline(&points, 25.0, None).0.unwrap();
+        track.place_gates();
+        assert_eq!(2, track.gates.len(), "a track without checkpoints gets one halfway round");
+        assert!(track.segments[0].finish_line.is_some());
+        let finish = track.gates[1];
+        assert!(finish.crossed_by(&FVec::new(-10.0, 10.0), &FVec::new(10.0, -10.0)));
+        assert!(!finish.crossed_by(&FVec::new(10.0, -10.0), &FVec::new(-10.0, 10.0)), "driving the wrong way does not cross");
+        track.checkpoints = vec![10, 30];
+        track.place_gates();
+        assert_eq!(3, track.gates.len());
+    }
+
+    #[test]
+    fn test_corner_edge_distance() {
+        // the road of a lower left corner curves around the top right of the tile
+        let near_pivot = edge_distance(TrackSegmentType::LowerLeft, &tile(), &FVec::new(140.0, 10.0)).unwrap();
+        assert!(near_pivot > 100.0, "close to the pivot is well inside the road, got {}", near_pivot);
+        let far_corner = edge_distance(TrackSegmentType::LowerLeft, &tile(), &FVec::new(5.0, 145.0)).unwrap();
+        assert!(far_corner < 0.0, "the opposite corner is outside the boundary, got {}", far_corner);
+    }
+}
+
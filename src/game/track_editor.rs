@@ -0,0 +1,645 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use web_sys::HtmlImageElement;
+use super::*;
+use super::track::*;
+use super::track_file::{Origin, SegmentDetail, SegmentSpec};
+use super::surface::Surface;
+use super::pickup::{PickupKind, PickupSpot};
+use crate::browser::{download, load_image, pick_file};
+use crate::config::SpawnConfig;
+use crate::error::{GameError, TrackIssue};
+
+const CELL_SIZE: f64 = TRACK_SEG_WIDTH;
+const VIEW_COLUMNS: i32 = 10;
+const VIEW_ROWS: i32 = 5;
+const STATUS_Y: f64 = CELL_SIZE * VIEW_ROWS as f64;
+const GRID_SLOTS: usize = 4;
+const GRID_SPACING: f64 = 70.0;
+const NEW_TRACK_NAME: &str = "custom";
+
+pub(super) type GridCell = (i32, i32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tool {
+    Straight,
+    Corner,
+    Start,
+    Checkpoint,
+    Erase,
+}
+
+impl Tool {
+    const ALL: [Tool; 5] = [Tool::Straight, Tool::Corner, Tool::Start, Tool::Checkpoint, Tool::Erase];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Tool::Straight => "Straight",
+            Tool::Corner => "Corner",
+            Tool::Start => "Start",
+            Tool::Checkpoint => "Checkpoint",
+            Tool::Erase => "Erase",
+        }
+    }
+
+    // the number keys pick the tools in the order of `ALL`
+    fn key(index: usize) -> String {
+        format!("Digit{}", index + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tile {
+    track_type: TrackSegmentType,
+    surface: Surface,
+}
+
+// `heading` is the grid step cars drive in when they cross the start line
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StartLine {
+    cell: GridCell,
+    heading: (i32, i32),
+}
+
+fn step(cell: GridCell, by: (i32, i32)) -> GridCell {
+    (cell.0 + by.0, cell.1 + by.1)
+}
+
+fn cell_origin(cell: GridCell) -> FVec {
+    FVec::new(cell.0 as f64 * CELL_SIZE, cell.1 as f64 * CELL_SIZE)
+}
+
+fn cell_at(point: &FVec) -> GridCell {
+    ((point.x / CELL_SIZE).floor() as i32, (point.y / CELL_SIZE).floor() as i32)
+}
+
+// the editor works on grid cells, the track file is built by driving the loop from the start line
+#[derive(Debug, Clone)]
+pub struct TrackGrid {
+    name: String,
+    author: Option<String>,
+    description: Option<String>,
+    off_track: Surface,
+    tiles: HashMap<GridCell, Tile>,
+    start: Option<StartLine>,
+    checkpoints: Vec<GridCell>,
+    pit_lane: Option<GridCell>,
+    pickups: Vec<(GridCell, PickupKind)>,
+}
+
+impl TrackGrid {
+    pub fn new(name: &str) -> Self {
+        TrackGrid {
+            name: name.to_string(),
+            author: None,
+            description: None,
+            off_track: Surface::Grass,
+            tiles: HashMap::new(),
+            start: None,
+            checkpoints: vec![],
+            pit_lane: None,
+            pickups: vec![],
+        }
+    }
+
+    // a loop of neighbouring cells, the start line is on the first cell heading to the second
+    pub(super) fn from_loop(name: &str, cells: &[GridCell], checkpoints: &[usize]) -> Self {
+        let mut grid = TrackGrid::new(name);
+        let delta = |from: GridCell, to: GridCell| (to.0 - from.0, to.1 - from.1);
+        for (index, cell) in cells.iter().enumerate() {
+            let previous = cells[(index + cells.len() - 1) % cells.len()];
+            let next = cells[(index + 1) % cells.len()];
+            if let Some(dir) = TrackSegmentDirection::from_steps(delta(previous, *cell), delta(*cell, next)) {
+                grid.tiles.insert(*cell, Tile { track_type: dir.track_type(), surface: Surface::Asphalt });
+            }
+        }
+        if cells.len() > 1 {
+            grid.start = Some(StartLine { cell: cells[0], heading: delta(cells[0], cells[1]) });
+        }
+        grid.checkpoints = checkpoints.iter().filter_map(|index| cells.get(*index).cloned()).collect();
+        grid
+    }
+
+    // placing a tile of the same kind again turns it a quarter clockwise
+    fn place(&mut self, cell: GridCell, track_type: TrackSegmentType) {
+        let tile = match self.tiles.get(&cell) {
+            Some(tile) if tile.track_type.is_straight() == track_type.is_straight() => Tile { track_type: tile.track_type.rotated(), ..*tile },
+            Some(tile) => Tile { track_type, ..*tile },
+            None => Tile { track_type, surface: Surface::Asphalt },
+        };
+        self.tiles.insert(cell, tile);
+        self.tidy();
+    }
+
+    fn erase(&mut self, cell: GridCell) {
+        self.tiles.remove(&cell);
+        self.tidy();
+    }
+
+    // the start line goes on a straight, setting it again on the same cell turns the cars around
+    fn set_start(&mut self, cell: GridCell) {
+        let tile = match self.tiles.get(&cell) {
+            Some(tile) if tile.track_type.is_straight() => tile,
+            _ => return,
+        };
+        let openings = tile.track_type.openings();
+        let heading = match self.start {
+            Some(start) if start.cell == cell && start.heading == openings[1] => openings[0],
+            _ => openings[1],
+        };
+        self.start = Some(StartLine { cell, heading });
+        self.checkpoints.retain(|checkpoint| *checkpoint != cell);
+    }
+
+    fn toggle_checkpoint(&mut self, cell: GridCell) {
+        if !self.tiles.contains_key(&cell) || self.start.map_or(false, |start| start.cell == cell) {
+            return;
+        }
+        match self.checkpoints.iter().position(|checkpoint| *checkpoint == cell) {
+            Some(index) => {
+                self.checkpoints.remove(index);
+            },
+            None => self.checkpoints.push(cell),
+        }
+    }
+
+    // drops whatever no longer has a fitting tile under it
+    fn tidy(&mut self) {
+        let tiles = &self.tiles;
+        let start_fits = |start: &StartLine| tiles.get(&start.cell)
+            .map_or(false, |tile| tile.track_type.is_straight() && tile.track_type.openings().contains(&start.heading));
+        if !self.start.as_ref().map_or(true, start_fits) {
+            self.start = None;
+        }
+        if !self.pit_lane.map_or(true, |cell| tiles.get(&cell).map_or(false, |tile| tile.track_type.is_straight())) {
+            self.pit_lane = None;
+        }
+        self.checkpoints.retain(|cell| tiles.contains_key(cell));
+        self.pickups.retain(|(cell, _)| tiles.contains_key(cell));
+    }
+
+    // every cell of the loop in driving order, on failure the cell where the loop breaks
+    fn walk(&self) -> Result<Vec<(GridCell, TrackSegmentDirection)>, (Option<GridCell>, TrackIssue)> {
+        let start = self.start.ok_or((None, TrackIssue::NoStart))?;
+        let (mut cell, mut heading) = (start.cell, start.heading);
+        let mut segments = vec![];
+        loop {
+            let tile = self.tiles.get(&cell).ok_or((Some(cell), TrackIssue::NotClosed))?;
+            let entry = (-heading.0, -heading.1);
+            let openings = tile.track_type.openings();
+            let exit = openings.iter().find(|opening| **opening != entry).cloned();
+            let dir = match (openings.contains(&entry), exit) {
+                (true, Some(exit)) => TrackSegmentDirection::from_steps(heading, exit),
+                _ => None,
+            };
+            let dir = dir.ok_or((Some(cell), TrackIssue::Gap { segment: segments.len() }))?;
+            segments.push((cell, dir));
+            heading = dir.entry_exit().1;
+            cell = step(cell, heading);
+            if cell == start.cell {
+                if heading != start.heading {
+                    return Err((Some(cell), TrackIssue::NotClosed));
+                }
+                return Ok(segments);
+            }
+            if segments.len() > self.tiles.len() {
+                return Err((Some(cell), TrackIssue::NotClosed));
+            }
+        }
+    }
+
+    // cars line up behind the start line as long as the road behind it runs straight
+    fn start_grid(&self, start: &StartLine) -> Vec<SpawnConfig> {
+        let center = cell_origin(start.cell);
+        let center = FVec::new(center.x + CELL_SIZE / 2.0, center.y + CELL_SIZE / 2.0);
+        let rotate = (start.heading.1 as f64).atan2(start.heading.0 as f64);
+        let mut grid = vec![];
+        for slot in 0..GRID_SLOTS {
+            let back = GRID_SPACING * slot as f64;
+            let point = FVec::new(center.x - start.heading.0 as f64 * back, center.y - start.heading.1 as f64 * back);
+            let straight = self.tiles.get(&cell_at(&point))
+                .map_or(false, |tile| tile.track_type.is_straight() && tile.track_type.openings().contains(&start.heading));
+            if slot > 0 && !straight {
+                break;
+            }
+            grid.push(SpawnConfig { x: point.x, y: point.y, rotate });
+        }
+        grid
+    }
+
+    pub fn to_file(&self) -> Result<TrackFile, GameError> {
+        let invalid = |issue: TrackIssue| GameError::InvalidTrack { name: self.name.clone(), issues: vec![issue] };
+        let segments = self.walk().map_err(|(_, issue)| invalid(issue))?;
+        let start = self.start.ok_or_else(|| invalid(TrackIssue::NoStart))?;
+        let index: HashMap<GridCell, usize> = segments.iter().enumerate().map(|(index, (cell, _))| (*cell, index)).collect();
+        let mut checkpoints: Vec<usize> = self.checkpoints.iter().filter_map(|cell| index.get(cell).cloned()).collect();
+        checkpoints.sort();
+        let origin = cell_origin(start.cell);
+        let file = TrackFile {
+            name: self.name.clone(),
+            author: self.author.clone(),
+            description: self.description.clone(),
+            origin: Origin { x: origin.x, y: origin.y },
+            start_grid: self.start_grid(&start),
+            segments: segments.iter().map(|(cell, dir)| {
+                match self.tiles.get(cell).map_or(Surface::Asphalt, |tile| tile.surface) {
+                    Surface::Asphalt => SegmentSpec::Tile(dir.name().to_string()),
+                    surface => SegmentSpec::Detailed(SegmentDetail { tile: dir.name().to_string(), surface }),
+                }
+            }).collect(),
+            centerline: vec![],
+            resolution: None,
+            checkpoints,
+            off_track: self.off_track,
+            pit_lane: self.pit_lane.and_then(|cell| index.get(&cell).cloned()),
+            pickups: self.pickups.iter()
+                .filter_map(|(cell, kind)| index.get(cell).map(|segment| PickupSpot { segment: *segment, kind: *kind }))
+                .collect(),
+        };
+        file.validate()?;
+        Ok(file)
+    }
+
+    // origins off the grid are snapped to the nearest cell
+    pub fn from_file(file: &TrackFile) -> Result<Self, GameError> {
+        let directions = file.validate()?;
+        // curves can not be put on the grid
+        if !file.is_tiled() {
+            return Err(GameError::InvalidTrack { name: file.name.clone(), issues: vec![TrackIssue::NotTiled] });
+        }
+        let mut grid = TrackGrid::new(&file.name);
+        grid.author = file.author.clone();
+        grid.description = file.description.clone();
+        grid.off_track = file.off_track;
+        let mut cell = ((file.origin.x / CELL_SIZE).round() as i32, (file.origin.y / CELL_SIZE).round() as i32);
+        let mut cells = vec![];
+        for (dir, spec) in directions.iter().zip(file.segments.iter()) {
+            grid.tiles.insert(cell, Tile { track_type: dir.track_type(), surface: spec.surface() });
+            cells.push(cell);
+            cell = step(cell, dir.entry_exit().1);
+        }
+        grid.start = cells.first().zip(directions.first()).map(|(cell, dir)| StartLine { cell: *cell, heading: dir.entry_exit().0 });
+        grid.checkpoints = file.checkpoints.iter().filter_map(|segment| cells.get(*segment).cloned()).collect();
+        grid.pit_lane = file.pit_lane.and_then(|segment| cells.get(segment).cloned());
+        grid.pickups = file.pickups.iter().filter_map(|spot| cells.get(spot.segment).map(|cell| (*cell, spot.kind))).collect();
+        Ok(grid)
+    }
+}
+
+pub struct TrackEditorStage {
+    grid: TrackGrid,
+    tool: Tool,
+    // the top left cell of the view
+    scroll: GridCell,
+    message: Option<String>,
+    images: HashMap<TrackSegmentType, HtmlImageElement>,
+    // the file dialog finishes on a later tick
+    imported: Rc<RefCell<Option<Result<TrackFile, GameError>>>>,
+    keyboard_state: Rc<RefCell<KeyboardState>>,
+    mouse_state: Rc<RefCell<MouseState>>,
+}
+
+impl TrackEditorStage {
+    pub async fn new(keyboard_state: Rc<RefCell<KeyboardState>>, mouse_state: Rc<RefCell<MouseState>>) -> Result<Box<Self>, GameError> {
+        use TrackSegmentType::*;
+        let mut images = HashMap::new();
+        for track_type in [UpDown, LeftRight, LowerLeft, LowerRight, UpperLeft, UpperRight].iter() {
+            if let Some(src) = track_type.image_src() {
+                images.insert(*track_type, load_image(src).await?);
+            }
+        }
+        Ok(Box::new(TrackEditorStage {
+            grid: TrackGrid::new(NEW_TRACK_NAME),
+            tool: Tool::Straight,
+            scroll: (0, 0),
+            message: None,
+            images,
+            imported: Rc::new(RefCell::new(None)),
+            keyboard_state,
+            mouse_state,
+        }))
+    }
+
+    // the grid cell under a point on the canvas, the status bar is not part of the grid
+    fn cell_under(&self, point: &FVec) -> Option<GridCell> {
+        if point.y >= STATUS_Y {
+            return None;
+        }
+        Some(step(cell_at(point), self.scroll))
+    }
+
+    fn apply_tool(&mut self, cell: GridCell) {
+        match self.tool {
+            Tool::Straight => self.grid.place(cell, TrackSegmentType::LeftRight),
+            Tool::Corner => self.grid.place(cell, TrackSegmentType::LowerLeft),
+            Tool::Start => self.grid.set_start(cell),
+            Tool::Checkpoint => self.grid.toggle_checkpoint(cell),
+            Tool::Erase => self.grid.erase(cell),
+        }
+    }
+
+    fn import(&self) {
+        let imported = self.imported.clone();
+        spawn_local(async move {
+            let file = match pick_file(".json").await {
+                Ok(json) => TrackFile::from_json(&json),
+                Err(err) => Err(err),
+            };
+            *imported.borrow_mut() = Some(file);
+        });
+    }
+
+    fn export(&mut self) {
+        self.message = match self.grid.to_file() {
+            Ok(file) => match download(&format!("{}.json", file.name), "application/json", &file.to_json()) {
+                Ok(()) => Some(format!("exported {}.json", file.name)),
+                Err(err) => Some(err.to_string()),
+            },
+            Err(err) => Some(err.to_string()),
+        };
+    }
+
+    fn draw_tile(&self, renderer: &Renderer, cell: GridCell, tile: &Tile) {
+        let origin = cell_origin(cell);
+        let rect = Rect { x: origin.x, y: origin.y, w: CELL_SIZE, h: CELL_SIZE };
+        if let Some(image) = self.images.get(&tile.track_type) {
+            renderer.draw_image_with_dest(image, &rect);
+        }
+        if tile.surface != Surface::Asphalt {
+            renderer.global_alpha(0.5);
+            renderer.fill(&rect, tile.surface.color());
+            renderer.global_alpha(1.0);
+        }
+        if self.grid.pit_lane == Some(cell) {
+            renderer.fill(&rect, "rgba(255, 220, 0, 0.25)");
+        }
+        renderer.stroke_style("orange");
+        renderer.line_width(2.0);
+        for line in TrackSegment::boundary_lines(tile.track_type, origin.x, origin.y) {
+            renderer.line(&line);
+        }
+    }
+
+    fn draw_start(&self, renderer: &Renderer, start: &StartLine) {
+        let origin = cell_origin(start.cell);
+        let center = FVec::new(origin.x + CELL_SIZE / 2.0, origin.y + CELL_SIZE / 2.0);
+        let (dx, dy) = (start.heading.0 as f64, start.heading.1 as f64);
+        // the line runs across the road, the arrow points the way the cars drive
+        let half = CELL_SIZE / 2.0;
+        renderer.stroke_style("white");
+        renderer.line_width(6.0);
+        renderer.line(&Line::new(center.x - dy * half, center.y - dx * half, center.x + dy * half, center.y + dx * half));
+        let tip = FVec::new(center.x + dx * 40.0, center.y + dy * 40.0);
+        let base = FVec::new(center.x + dx * 15.0, center.y + dy * 15.0);
+        renderer.fill_style("white");
+        renderer.polygon(&[
+            tip,
+            FVec::new(base.x - dy * 15.0, base.y - dx * 15.0),
+            FVec::new(base.x + dy * 15.0, base.y + dx * 15.0),
+        ], true);
+    }
+
+    fn draw_checkpoint(&self, renderer: &Renderer, cell: GridCell, label: &str) {
+        let origin = cell_origin(cell);
+        renderer.stroke_style("#3fa9f5");
+        renderer.line_width(4.0);
+        renderer.rounded_rect(&Rect { x: origin.x + 6.0, y: origin.y + 6.0, w: CELL_SIZE - 12.0, h: CELL_SIZE - 12.0 }, 8.0, false);
+        renderer.fill_style("#3fa9f5");
+        renderer.font("bold 20px sans-serif");
+        renderer.text_align(TextAlign::Center);
+        renderer.text_baseline(TextBaseline::Middle);
+        renderer.fill_text(label, FVec::new(origin.x + CELL_SIZE / 2.0, origin.y + CELL_SIZE / 2.0));
+    }
+
+    fn draw_status(&self, renderer: &Renderer, status: &str) {
+        renderer.fill(&Rect { x: 0.0, y: STATUS_Y, w: CELL_SIZE * VIEW_COLUMNS as f64, h: 50.0 }, "rgba(0, 0, 0, 0.8)");
+        renderer.font("16px sans-serif");
+        renderer.text_align(TextAlign::Left);
+        renderer.text_baseline(TextBaseline::Middle);
+        let mut x = 10.0;
+        for (index, tool) in Tool::ALL.iter().enumerate() {
+            let label = format!("{} {}", index + 1, tool.label());
+            renderer.fill_style(if *tool == self.tool { "yellow" } else { "white" });
+            renderer.fill_text(&label, FVec::new(x, STATUS_Y + 14.0));
+            x += renderer.measure_text(&label) + 20.0;
+        }
+        renderer.fill_style("white");
+        renderer.fill_text("T test drive  E export  I import  N new  arrows scroll  right click erase  Esc back", FVec::new(x + 20.0, STATUS_Y + 14.0));
+        renderer.fill_text(&format!("{}: {}", self.grid.name, self.message.as_deref().unwrap_or(status)), FVec::new(10.0, STATUS_Y + 36.0));
+    }
+}
+
+impl Stage for TrackEditorStage {
+    fn claims_key(&self, code: &str) -> bool {
+        (0..Tool::ALL.len()).any(|index| Tool::key(index) == code)
+    }
+
+    fn update(&mut self, _delta: f64) -> Transition {
+        if let Some(imported) = self.imported.borrow_mut().take() {
+            self.message = match imported.and_then(|file| TrackGrid::from_file(&file)) {
+                Ok(grid) => {
+                    self.grid = grid;
+                    self.scroll = (0, 0);
+                    Some(format!("imported {}", self.grid.name))
+                },
+                Err(err) => Some(err.to_string()),
+            };
+        }
+        let clicks: Vec<(FVec, MouseButton)> = self.mouse_state.borrow().clicks().to_vec();
+        for (point, button) in clicks {
+            if let Some(cell) = self.cell_under(&point) {
+                self.message = None;
+                match button {
+                    MouseButton::Primary => self.apply_tool(cell),
+                    MouseButton::Secondary => self.grid.erase(cell),
+                }
+            }
+        }
+        let (exit, new, import, export, test_drive) = {
+            let keyboard_state = self.keyboard_state.borrow();
+            for (index, tool) in Tool::ALL.iter().enumerate() {
+                if keyboard_state.was_pressed(&Tool::key(index)) {
+                    self.tool = *tool;
+                }
+            }
+            let pans = [("ArrowUp", (0, -1)), ("ArrowDown", (0, 1)), ("ArrowLeft", (-1, 0)), ("ArrowRight", (1, 0))];
+            for (key, by) in pans.iter() {
+                if keyboard_state.was_pressed(key) {
+                    self.scroll = step(self.scroll, *by);
+                }
+            }
+            (keyboard_state.was_pressed("Escape"), keyboard_state.was_pressed("KeyN"), keyboard_state.was_pressed("KeyI"),
+                keyboard_state.was_pressed("KeyE"), keyboard_state.was_pressed("KeyT"))
+        };
+        if exit {
+            return Transition::Pop;
+        }
+        if new {
+            self.grid = TrackGrid::new(NEW_TRACK_NAME);
+            self.message = None;
+        }
+        if import {
+            self.import();
+        }
+        if export {
+            self.export();
+        }
+        if test_drive {
+            match self.grid.to_file() {
+                Ok(file) => return Transition::Push(StageRequest::TestDrive(file)),
+                Err(err) => self.message = Some(err.to_string()),
+            }
+        }
+        Transition::None
+    }
+
+    fn draw(&self, renderer: &Renderer) {
+        let walk = self.grid.walk();
+        renderer.save();
+        renderer.fill(&Rect { x: 0.0, y: 0.0, w: CELL_SIZE * VIEW_COLUMNS as f64, h: STATUS_Y }, self.grid.off_track.color());
+        let offset = cell_origin(self.scroll);
+        renderer.translate(&FVec::new(-offset.x, -offset.y));
+        renderer.stroke_style("rgba(255, 255, 255, 0.2)");
+        renderer.line_width(1.0);
+        for column in 0..=VIEW_COLUMNS {
+            let x = cell_origin(step(self.scroll, (column, 0))).x;
+            renderer.line(&Line::new(x, offset.y, x, offset.y + STATUS_Y));
+        }
+        for row in 0..=VIEW_ROWS {
+            let y = cell_origin(step(self.scroll, (0, row))).y;
+            renderer.line(&Line::new(offset.x, y, offset.x + CELL_SIZE * VIEW_COLUMNS as f64, y));
+        }
+        for (cell, tile) in self.grid.tiles.iter() {
+            self.draw_tile(renderer, *cell, tile);
+        }
+        // checkpoints are numbered in driving order once the loop is closed
+        for (index, cell) in self.grid.checkpoints.iter().enumerate() {
+            let order = walk.as_ref().ok()
+                .and_then(|segments| {
+                    let mut checkpoints: Vec<usize> = self.grid.checkpoints.iter()
+                        .filter_map(|checkpoint| segments.iter().position(|(segment, _)| segment == checkpoint))
+                        .collect();
+                    checkpoints.sort();
+                    let segment = segments.iter().position(|(segment, _)| segment == cell)?;
+                    checkpoints.iter().position(|checkpoint| *checkpoint == segment)
+                })
+                .unwrap_or(index);
+            self.draw_checkpoint(renderer, *cell, &(order + 1).to_string());
+        }
+        if let Some(start) = self.grid.start.as_ref() {
+            self.draw_start(renderer, start);
+        }
+        if let Err((Some(cell), _)) = walk.as_ref() {
+            let origin = cell_origin(*cell);
+            renderer.stroke_style("red");
+            renderer.line_width(4.0);
+            renderer.rounded_rect(&Rect { x: origin.x + 2.0, y: origin.y + 2.0, w: CELL_SIZE - 4.0, h: CELL_SIZE - 4.0 }, 4.0, false);
+        }
+        if let Some(cell) = self.mouse_state.borrow().position().and_then(|point| self.cell_under(&point)) {
+            let origin = cell_origin(cell);
+            renderer.fill(&Rect { x: origin.x, y: origin.y, w: CELL_SIZE, h: CELL_SIZE }, "rgba(255, 255, 255, 0.2)");
+        }
+        renderer.restore();
+        let status = match walk {
+            Ok(segments) => {
+                let unused = self.grid.tiles.len() - segments.len();
+                match unused {
+                    0 => format!("closed loop of {} segments", segments.len()),
+                    _ => format!("closed loop of {} segments, {} tiles off the loop are left out", segments.len(), unused),
+                }
+            },
+            Err((_, issue)) => issue.to_string(),
+        };
+        self.draw_status(renderer, &status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TrackSegmentType::*;
+
+    // a 3 by 2 loop, the start is on the top straight heading right
+    fn small_loop() -> TrackGrid {
+        let mut grid = TrackGrid::new("test");
+        let tiles = [((0, 0), UpperLeft), ((1, 0), LeftRight), ((2, 0), UpperRight), ((2, 1), LowerRight), ((1, 1), LeftRight), ((0, 1), LowerLeft)];
+        for (cell, track_type) in tiles.iter().cloned() {
+            grid.tiles.insert(cell, Tile { track_type, surface: Surface::Asphalt });
+        }
+        grid.set_start((1, 0));
+        grid
+    }
+
+    #[test]
+    fn test_closed_loop() {
+        let mut grid = small_loop();
+        grid.toggle_checkpoint((1, 1));
+        let file = grid.to_file().unwrap();
+        let names: Vec<&str> = file.segments.iter().map(|spec| spec.tile()).collect();
+        assert_eq!(vec!["right", "down_right_down", "down_left_left", "left", "up_left_up", "up_right_right"], names);
+        assert_eq!(vec![3], file.checkpoints);
+        assert_eq!(CELL_SIZE, file.origin.x);
+        assert_eq!(2, file.start_grid.len(), "two cars fit on a single straight");
+        assert_eq!(0.0, file.start_grid[0].rotate);
+    }
+
+    #[test]
+    fn test_reversed_start() {
+        let mut grid = small_loop();
+        grid.set_start((1, 0));
+        let file = grid.to_file().unwrap();
+        assert_eq!("left", file.segments[0].tile());
+        assert!((file.start_grid[0].rotate - PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_open_loop() {
+        let mut grid = small_loop();
+        grid.erase((0, 1));
+        assert_eq!(Err((Some((0, 1)), TrackIssue::NotClosed)), grid.walk());
+        assert!(grid.to_file().is_err());
+        grid.place((0, 1), LowerLeft);
+        assert!(grid.walk().is_ok());
+        grid.place((0, 1), LowerLeft);
+        assert_eq!(Err((Some((0, 2)), TrackIssue::NotClosed)), grid.walk(), "the rotated corner leads off the loop");
+        grid.place((0, 1), LowerLeft);
+        assert_eq!(Err((Some((0, 1)), TrackIssue::Gap { segment: 4 })), grid.walk(), "the corner no longer connects");
+    }
+
+    #[test]
+    fn test_start_needs_straight() {
+        let mut grid = small_loop();
+        grid.erase((1, 0));
+        assert_eq!(None, grid.start, "the start goes with its tile");
+        grid.set_start((0, 0));
+        assert_eq!(Err((None, TrackIssue::NoStart)), grid.walk());
+        grid.set_start((1, 1));
+        assert!(grid.start.is_some());
+        grid.place((1, 1), UpDown);
+        assert_eq!(None, grid.start, "the start no longer fits the rotated straight");
+    }
+
+    #[test]
+    fn test_start_grid_follows_straights() {
+        let mut grid = TrackGrid::new("test");
+        for x in 0..4 {
+            grid.tiles.insert((x, 0), Tile { track_type: LeftRight, surface: Surface::Asphalt });
+        }
+        grid.set_start((3, 0));
+        let start = grid.start.unwrap();
+        assert_eq!(GRID_SLOTS, grid.start_grid(&start).len());
+        grid.tiles.insert((2, 0), Tile { track_type: LowerLeft, surface: Surface::Asphalt });
+        assert_eq!(2, grid.start_grid(&start).len(), "the grid stops at the corner");
+    }
+
+    #[test]
+    fn test_builtin_round_trip() {
+        for file in TrackFile::builtin().into_iter().filter(|file| file.is_tiled()) {
+            let exported = TrackGrid::from_file(&file).unwrap().to_file().unwrap();
+            assert_eq!(file.segments, exported.segments, "{}", file.name);
+            assert_eq!(file.checkpoints, exported.checkpoints, "{}", file.name);
+            assert_eq!(file.pit_lane, exported.pit_lane, "{}", file.name);
+            assert_eq!(file.pickups, exported.pickups, "{}", file.name);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use serde::{Serialize, Deserialize};
+use crate::engine::network::get;
+use crate::config::SpawnConfig;
+use crate::error::{GameError, TrackIssue};
+use super::track::{TrackSegmentDirection, layout_issues, pit_lane_issues, spline_pit_lane_issues};
+use super::surface::Surface;
+use super::pickup::PickupSpot;
+use super::spline::{ControlPoint, DEFAULT_RESOLUTION, spline_issues};
+
+pub const TRACKS_URL: &str = "tracks/index.json";
+
+// the tracks that ship with the game, used when the track files can not be fetched
+const BUILTIN_TRACKS: [&str; 3] = [
+    include_str!("../../static/tracks/classic.json"),
+    include_str!("../../static/tracks/oval.json"),
+    include_str!("../../static/tracks/ribbon.json"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Origin {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SegmentDetail {
+    pub tile: String,
+    #[serde(default)]
+    pub surface: Surface,
+}
+
+// a segment is either just its tile name or a tile with a surface
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SegmentSpec {
+    Tile(String),
+    Detailed(SegmentDetail),
+}
+
+impl SegmentSpec {
+    pub fn tile(&self) -> &str {
+        match self {
+            SegmentSpec::Tile(tile) => tile,
+            SegmentSpec::Detailed(detail) => &detail.tile,
+        }
+    }
+
+    pub fn surface(&self) -> Surface {
+        match self {
+            SegmentSpec::Tile(_) => Surface::Asphalt,
+            SegmentSpec::Detailed(detail) => detail.surface,
+        }
+    }
+}
+
+fn default_off_track() -> Surface {
+    Surface::Grass
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackFile {
+    pub name: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub origin: Origin,
+    // where cars line up, the first slot is pole position
+    #[serde(default)]
+    pub start_grid: Vec<SpawnConfig>,
+    // a track is laid out either from tiles or as a closed curve through the centerline points,
+    // for a curve the segment indices below refer to the centerline points
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<SegmentSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub centerline: Vec<ControlPoint>,
+    // how long the pieces of a curve are at most, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<f64>,
+    #[serde(default)]
+    pub checkpoints: Vec<usize>,
+    #[serde(default = "default_off_track")]
+    pub off_track: Surface,
+    #[serde(default)]
+    pub pit_lane: Option<usize>,
+    #[serde(default)]
+    pub pickups: Vec<PickupSpot>,
+}
+
+impl TrackFile {
+    pub fn from_json(json: &str) -> Result<Self, GameError> {
+        let file: TrackFile = serde_json::from_str(json).map_err(|err| GameError::InvalidTrack {
+            name: String::new(),
+            issues: vec![TrackIssue::Parse(err.to_string())],
+        })?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn is_tiled(&self) -> bool {
+        self.centerline.is_empty()
+    }
+
+    pub fn resolution(&self) -> f64 {
+        self.resolution.unwrap_or(DEFAULT_RESOLUTION)
+    }
+
+    pub fn builtin() -> Vec<TrackFile> {
+        BUILTIN_TRACKS.iter()
+            .filter_map(|json| TrackFile::from_json(json).ok())
+            .collect()
+    }
+
+    // checks everything that can be checked without loading the tiles, returns the parsed directions of a tile track
+    pub fn validate(&self) -> Result<Vec<TrackSegmentDirection>, GameError> {
+        let mut issues = vec![];
+        if !self.segments.is_empty() && !self.centerline.is_empty() {
+            issues.push(TrackIssue::MixedLayout);
+        } else if !self.centerline.is_empty() {
+            issues.extend(spline_issues(&self.centerline, self.resolution()));
+            if issues.is_empty() {
+                // a pit lane off the end of the track is reported as a missing segment below
+                issues.extend(spline_pit_lane_issues(&self.centerline, self.resolution(), self.pit_lane.filter(|segment| *segment < self.centerline.len())));
+            }
+        } else if self.segments.is_empty() {
+            issues.push(TrackIssue::Empty);
+        }
+        let directions: Vec<Option<TrackSegmentDirection>> = self.segments.iter().enumerate()
+            .map(|(segment, spec)| {
+                let dir = TrackSegmentDirection::from_name(spec.tile());
+                if dir.is_none() {
+                    issues.push(TrackIssue::UnknownTile { segment, tile: spec.tile().to_string() });
+                }
+                dir
+            })
+            .collect();
+        // the layout can only be placed once every tile is known
+        if issues.is_empty() && self.is_tiled() {
+            let known: Vec<TrackSegmentDirection> = directions.iter().flatten().cloned().collect();
+            issues.extend(layout_issues(&known));
+            // a pit lane off the end of the track is reported as a missing segment below
+            issues.extend(pit_lane_issues(&known, self.pit_lane.filter(|segment| *segment < known.len())));
+        }
+        let indices = self.checkpoints.iter().map(|segment| ("checkpoints", *segment))
+            .chain(self.pickups.iter().map(|spot| ("pickups", spot.segment)))
+            .chain(self.pit_lane.iter().map(|segment| ("pit_lane", *segment)));
+        let count = if self.is_tiled() { self.segments.len() } else { self.centerline.len() };
+        for (field, segment) in indices {
+            if segment >= count {
+                issues.push(TrackIssue::MissingSegment { field: field.to_string(), segment });
+            }
+        }
+        if !issues.is_empty() {
+            return Err(GameError::InvalidTrack { name: self.name.clone(), issues });
+        }
+        Ok(directions.into_iter().flatten().collect())
+    }
+}
+
+// fetches every track listed in the index, broken files are reported and left out
+pub async fn load_tracks(index_url: &str) -> Vec<TrackFile> {
+    let base = index_url.rsplit_once('/').map(|(base, _)| format!("{}/", base)).unwrap_or_default();
+    let names: Vec<String> = match get(index_url).await {
+        Ok(text) => serde_json::from_str(&text.as_string().unwrap_or_default()).unwrap_or_else(|err| {
+            crate::log_error!("invalid track index {}: {}", index_url, err);
+            vec![]
+        }),
+        Err(err) => {
+            crate::log_warn!("could not fetch {}: {:?}", index_url, err);
+            vec![]
+        },
+    };
+    let mut tracks = vec![];
+    for name in names {
+        let url = format!("{}{}", base, name);
+        match get(&url).await {
+            Ok(text) => match TrackFile::from_json(&text.as_string().unwrap_or_default()) {
+                Ok(track) => tracks.push(track),
+                Err(err) => crate::log_error!("{}: {}", url, err),
+            },
+            Err(err) => crate::log_error!("could not fetch {}: {:?}", url, err),
+        }
+    }
+    if tracks.is_empty() {
+        crate::log_warn!("no track files loaded, using the built in tracks");
+        return TrackFile::builtin();
+    }
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 3 by 2 loop
+    const SMALL_LOOP: &str = r#"["right", "down_right_down", "down_left_left", "left", "up_left_up", "up_right_right"]"#;
+
+    fn track(segments: &str) -> Result<TrackFile, GameError> {
+        TrackFile::from_json(&format!(r#"{{ "name": "test", "segments": {} }}"#, segments))
+    }
+
+    fn issues(result: Result<TrackFile, GameError>) -> Vec<TrackIssue> {
+        match result {
+            Err(GameError::InvalidTrack { issues, .. }) => issues,
+            other => panic!("expected an invalid track, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_builtin_tracks_are_valid() {
+        for json in BUILTIN_TRACKS.iter() {
+            if let Err(err) = TrackFile::from_json(json) {
+                panic!("{}", err);
+            }
+        }
+        assert_eq!(BUILTIN_TRACKS.len(), TrackFile::builtin().len());
+    }
+
+    #[test]
+    fn test_segment_forms() {
+        let track = track(r#"["right", "down_right_down", "down_left_left", { "tile": "left", "surface": "ice" }, "up_left_up", "up_right_right"]"#).unwrap();
+        assert_eq!(Surface::Asphalt, track.segments[0].surface());
+        assert_eq!(Surface::Ice, track.segments[3].surface());
+        assert_eq!(Surface::Grass, track.off_track);
+    }
+
+    #[test]
+    fn test_unknown_tile() {
+        assert_eq!(vec![TrackIssue::UnknownTile { segment: 1, tile: "diagonal".to_string() }], issues(track(r#"["right", "diagonal"]"#)));
+    }
+
+    #[test]
+    fn test_gap() {
+        assert_eq!(vec![TrackIssue::Gap { segment: 2 }, TrackIssue::NotClosed], issues(track(r#"["right", "right", "up"]"#)));
+    }
+
+    #[test]
+    fn test_overlap() {
+        let segments = r#"["right", "down_right_down", "down_left_left", "up_left_up", "up"]"#;
+        assert_eq!(vec![TrackIssue::Overlap { segment: 4, other: 0 }, TrackIssue::NotClosed], issues(track(segments)));
+    }
+
+    #[test]
+    fn test_not_closed() {
+        assert_eq!(vec![TrackIssue::NotClosed], issues(track(r#"["right", "right"]"#)));
+        assert!(track(SMALL_LOOP).is_ok());
+    }
+
+    #[test]
+    fn test_missing_segment() {
+        let result = TrackFile::from_json(&format!(r#"{{ "name": "test", "segments": {}, "checkpoints": [8], "pit_lane": 0 }}"#, SMALL_LOOP));
+        assert_eq!(vec![TrackIssue::MissingSegment { field: "checkpoints".to_string(), segment: 8 }], issues(result));
+    }
+
+    const SQUARE: &str = r#"[{ "x": 0, "y": 0, "width": 100 }, { "x": 400, "y": 0, "width": 80 }, { "x": 400, "y": 400, "width": 100, "surface": "ice" }, { "x": 0, "y": 400, "width": 100 }]"#;
+
+    #[test]
+    fn test_centerline() {
+        let track = TrackFile::from_json(&format!(r#"{{ "name": "test", "centerline": {}, "checkpoints": [2] }}"#, SQUARE)).unwrap();
+        assert!(!track.is_tiled());
+        assert_eq!(Surface::Ice, track.centerline[2].surface);
+        assert_eq!(DEFAULT_RESOLUTION, track.resolution());
+        let result = TrackFile::from_json(&format!(r#"{{ "name": "test", "centerline": {}, "checkpoints": [4] }}"#, SQUARE));
+        assert_eq!(vec![TrackIssue::MissingSegment { field: "checkpoints".to_string(), segment: 4 }], issues(result));
+        let result = TrackFile::from_json(&format!(r#"{{ "name": "test", "centerline": {}, "resolution": 0 }}"#, SQUARE));
+        assert_eq!(vec![TrackIssue::InvalidResolution], issues(result));
+    }
+
+    #[test]
+    fn test_centerline_pit_lane() {
+        let centerline = r#"[{ "x": 0, "y": 0, "width": 50 }, { "x": 200, "y": 0, "width": 50 }, { "x": 400, "y": 0, "width": 50 }, { "x": 600, "y": 0, "width": 50 }, { "x": 600, "y": 400, "width": 50 }, { "x": 0, "y": 400, "width": 50 }]"#;
+        let with_pit_lane = |segment: usize| TrackFile::from_json(&format!(r#"{{ "name": "test", "centerline": {}, "pit_lane": {} }}"#, centerline, segment));
+        assert!(with_pit_lane(1).is_ok(), "the span between points in line is straight");
+        assert_eq!(vec![TrackIssue::PitLaneNotStraight { segment: 3 }], issues(with_pit_lane(3)));
+        assert_eq!(vec![TrackIssue::MissingSegment { field: "pit_lane".to_string(), segment: 6 }], issues(with_pit_lane(6)));
+    }
+
+    #[test]
+    fn test_mixed_layout() {
+        let result = TrackFile::from_json(&format!(r#"{{ "name": "test", "centerline": {}, "segments": {} }}"#, SQUARE, SMALL_LOOP));
+        assert_eq!(vec![TrackIssue::MixedLayout], issues(result));
+        assert_eq!(vec![TrackIssue::Empty], issues(TrackFile::from_json(r#"{ "name": "test" }"#)));
+    }
+
+    #[test]
+    fn test_pit_lane() {
+        let with_pit_lane = |segment: usize| TrackFile::from_json(&format!(r#"{{ "name": "test", "segments": {}, "pit_lane": {} }}"#, SMALL_LOOP, segment));
+        assert!(with_pit_lane(0).is_ok());
+        assert_eq!(vec![TrackIssue::PitLaneNotStraight { segment: 1 }], issues(with_pit_lane(1)));
+        assert_eq!(vec![TrackIssue::MissingSegment { field: "pit_lane".to_string(), segment: 9 }], issues(with_pit_lane(9)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let track = TrackFile::builtin().remove(0);
+        assert_eq!(track, TrackFile::from_json(&track.to_json()).unwrap());
+    }
+}
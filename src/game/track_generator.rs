@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+use super::TrackFile;
+use super::track_editor::{GridCell, TrackGrid};
+use crate::config::{GameConfig, GeneratorConfig};
+use crate::error::GameError;
+
+// at the highest difficulty notches fill this share of the inside of the rectangle
+const NOTCH_SHARE: f64 = 0.5;
+const ATTEMPTS_PER_TILE: i32 = 20;
+// checkpoints split a generated lap into this many parts
+const CHECKPOINT_PARTS: usize = 4;
+
+// splitmix64, small and good enough to lay out tracks
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// the outline of a width by height rectangle, clockwise from the middle of the top row
+fn ring(width: i32, height: i32) -> Vec<GridCell> {
+    let mut cells = vec![];
+    for x in 0..width {
+        cells.push((x, 0));
+    }
+    for y in 1..height {
+        cells.push((width - 1, y));
+    }
+    for x in (0..width - 1).rev() {
+        cells.push((x, height - 1));
+    }
+    for y in (1..height - 1).rev() {
+        cells.push((0, y));
+    }
+    cells.rotate_left((width / 2) as usize);
+    cells
+}
+
+// moves the road between two neighbouring cells one cell sideways,
+// the loop stays closed and can not overlap itself because both new cells have to be free
+fn notch(cells: &mut Vec<GridCell>, occupied: &mut HashSet<GridCell>, edge: usize, left: bool, width: i32, height: i32) -> bool {
+    let (a, b) = (cells[edge], cells[(edge + 1) % cells.len()]);
+    let along = (b.0 - a.0, b.1 - a.1);
+    let by = if left { (along.1, -along.0) } else { (-along.1, along.0) };
+    let (new_a, new_b) = ((a.0 + by.0, a.1 + by.1), (b.0 + by.0, b.1 + by.1));
+    let free = |cell: &GridCell| cell.0 >= 0 && cell.1 >= 0 && cell.0 < width && cell.1 < height && !occupied.contains(cell);
+    if !free(&new_a) || !free(&new_b) {
+        return false;
+    }
+    cells.insert(edge + 1, new_b);
+    cells.insert(edge + 1, new_a);
+    occupied.insert(new_a);
+    occupied.insert(new_b);
+    true
+}
+
+pub fn generate_track(seed: u64, config: &GeneratorConfig) -> Result<TrackFile, GameError> {
+    let mut rng = Rng::new(seed);
+    let (width, height) = (config.width as i32, config.height as i32);
+    let mut cells = ring(width, height);
+    let mut occupied: HashSet<GridCell> = cells.iter().cloned().collect();
+    let inside = (width * height) as usize - cells.len();
+    let target = cells.len() + (inside as f64 * config.difficulty * NOTCH_SHARE) as usize;
+    let mut attempts = width * height * ATTEMPTS_PER_TILE;
+    while cells.len() < target && attempts > 0 {
+        attempts -= 1;
+        // the start line is the first cell and the straight behind it the last,
+        // edges touching either are left alone so the start grid stays straight
+        let edge = 1 + rng.below(cells.len() - 3);
+        notch(&mut cells, &mut occupied, edge, rng.below(2) == 0, width, height);
+    }
+    let checkpoints: Vec<usize> = (1..CHECKPOINT_PARTS).map(|part| cells.len() * part / CHECKPOINT_PARTS).collect();
+    let mut file = TrackGrid::from_loop(&format!("random-{}", seed), &cells, &checkpoints).to_file()?;
+    file.author = Some("track generator".to_string());
+    file.description = Some(format!("{} by {} tiles at difficulty {}", width, height, config.difficulty));
+    Ok(file)
+}
+
+// track `n` uses seed + n, a track can be made again from the seed in its name
+pub fn generate_tracks(config: &GameConfig) -> Vec<TrackFile> {
+    if config.generator.tracks == 0 {
+        return vec![];
+    }
+    let seed = config.seed.unwrap_or_else(|| uuid::Uuid::new_v4().as_u64_pair().0);
+    crate::log_info!("generating {} tracks from seed {}", config.generator.tracks, seed);
+    (0..config.generator.tracks as u64)
+        .filter_map(|n| match generate_track(seed.wrapping_add(n), &config.generator) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                crate::log_error!("{}", err);
+                None
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(width: u32, height: u32, difficulty: f64) -> GeneratorConfig {
+        GeneratorConfig { tracks: 1, width, height, difficulty }
+    }
+
+    #[test]
+    fn test_same_seed_same_track() {
+        let config = config(10, 5, 0.7);
+        assert_eq!(generate_track(7, &config).unwrap(), generate_track(7, &config).unwrap());
+        assert_ne!(generate_track(7, &config).unwrap().segments, generate_track(8, &config).unwrap().segments);
+    }
+
+    #[test]
+    fn test_tracks_are_valid() {
+        for (width, height, difficulty) in [(4, 3, 1.0), (10, 5, 0.5), (12, 8, 1.0)].iter() {
+            for seed in 0..200 {
+                let file = generate_track(seed, &config(*width, *height, *difficulty)).unwrap();
+                assert!(file.validate().is_ok(), "seed {}", seed);
+                assert_eq!(4, file.start_grid.len(), "seed {} should have a full start grid", seed);
+                assert_eq!(CHECKPOINT_PARTS - 1, file.checkpoints.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_difficulty() {
+        let plain = generate_track(3, &config(10, 5, 0.0)).unwrap();
+        assert_eq!(26, plain.segments.len(), "no difficulty is the plain rectangle");
+        let twisty = generate_track(3, &config(10, 5, 1.0)).unwrap();
+        assert!(twisty.segments.len() > plain.segments.len());
+    }
+
+    #[test]
+    fn test_stays_in_bounds() {
+        let (width, height) = (6, 4);
+        let mut cells = ring(width, height);
+        let mut occupied: HashSet<GridCell> = cells.iter().cloned().collect();
+        let mut rng = Rng::new(1);
+        for _ in 0..500 {
+            let edge = 1 + rng.below(cells.len() - 3);
+            notch(&mut cells, &mut occupied, edge, rng.below(2) == 0, width, height);
+        }
+        assert!(cells.iter().all(|cell| cell.0 >= 0 && cell.1 >= 0 && cell.0 < width && cell.1 < height));
+        assert_eq!(cells.len(), occupied.len(), "no cell is used twice");
+    }
+}
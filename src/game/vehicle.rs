@@ -0,0 +1,400 @@
+use crate::engine::FVec;
+use crate::config::CarConfig;
+use super::surface::SurfaceProperties;
+
+// lengths are in pixels, time in seconds, the default car sprite is 63px long
+// gravity is game scale so cornering grip stays in line with the 150px track tiles
+pub const GRAVITY: f64 = 1200.0;
+// the physics always integrates in steps of this length whatever the tick rate
+pub const FIXED_STEP: f64 = 1.0 / 240.0;
+const ROLLING_RESISTANCE: f64 = 0.1;
+// brake force as a multiple of the engine force
+const BRAKE_RATIO: f64 = 2.0;
+// share of the brake force a fully pulled handbrake applies
+const HANDBRAKE_RATIO: f64 = 0.5;
+// what a car about to retire has lost of its engine and steering
+const DAMAGED_ENGINE: f64 = 0.5;
+const DAMAGED_STEER: f64 = 0.3;
+// below this speed slip angles are meaningless and the car follows its wheels
+const KINEMATIC_SPEED: f64 = 40.0;
+const STOP_SPEED: f64 = 5.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleParams {
+    pub mass: f64,
+    pub inertia: f64,
+    pub cg_to_front: f64,
+    pub cg_to_rear: f64,
+    pub engine_force: f64,
+    pub brake_force: f64,
+    pub reverse_ratio: f64,
+    pub drag: f64,
+    pub rolling_resistance: f64,
+    pub cornering_stiffness: f64,
+    pub grip: f64,
+    pub max_steer: f64,
+    pub steer_speed: f64,
+    pub handbrake_grip: f64,
+}
+
+impl VehicleParams {
+    pub fn from_config(config: &CarConfig) -> Self {
+        let mass = config.mass;
+        let (length, width) = (config.width, config.height);
+        let rolling_resistance = ROLLING_RESISTANCE * mass;
+        let engine_force = config.engine_force;
+        VehicleParams {
+            mass,
+            inertia: mass * (length * length + width * width) / 12.0,
+            // the axles sit a little inside the sprite
+            cg_to_front: length * 0.32,
+            cg_to_rear: length * 0.32,
+            engine_force,
+            brake_force: BRAKE_RATIO * engine_force,
+            reverse_ratio: 0.5,
+            // drag and rolling resistance cancel the engine exactly at top speed
+            drag: (engine_force - rolling_resistance * config.top_speed) / (config.top_speed * config.top_speed),
+            rolling_resistance,
+            cornering_stiffness: 4000.0 * mass,
+            grip: config.grip,
+            max_steer: config.max_steer,
+            steer_speed: 4.0,
+            handbrake_grip: 0.4,
+        }
+    }
+
+    // the same car on a different surface, read again every tick
+    pub fn on_surface(&self, surface: &SurfaceProperties) -> Self {
+        VehicleParams {
+            grip: self.grip * surface.friction,
+            rolling_resistance: self.rolling_resistance + surface.drag * self.mass,
+            ..self.clone()
+        }
+    }
+
+    // fuel adds to the mass the engine has to move
+    pub fn fueled(&self, fuel: f64) -> Self {
+        let mass = self.mass + fuel.max(0.0);
+        VehicleParams {
+            mass,
+            inertia: self.inertia * mass / self.mass,
+            ..self.clone()
+        }
+    }
+
+    pub fn worn(&self, grip_factor: f64) -> Self {
+        VehicleParams {
+            grip: self.grip * grip_factor,
+            ..self.clone()
+        }
+    }
+
+    // damage goes from 0 for a fresh car to 1 for a retired one, a weaker engine also lowers the top speed
+    pub fn damaged(&self, damage: f64) -> Self {
+        let damage = damage.clamp(0.0, 1.0);
+        VehicleParams {
+            engine_force: self.engine_force * (1.0 - DAMAGED_ENGINE * damage),
+            max_steer: self.max_steer * (1.0 - DAMAGED_STEER * damage),
+            ..self.clone()
+        }
+    }
+
+    fn wheelbase(&self) -> f64 {
+        self.cg_to_front + self.cg_to_rear
+    }
+}
+
+// throttle, brake and handbrake range from 0 to 1, steer from -1 (left) to 1 (right)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ControlInput {
+    pub throttle: f64,
+    pub brake: f64,
+    pub steer: f64,
+    pub handbrake: f64,
+}
+
+impl ControlInput {
+    pub fn clamped(&self) -> Self {
+        let clamp = |value: f64| if value.is_finite() { value.clamp(0.0, 1.0) } else { 0.0 };
+        ControlInput {
+            throttle: clamp(self.throttle),
+            brake: clamp(self.brake),
+            steer: if self.steer.is_finite() { self.steer.clamp(-1.0, 1.0) } else { 0.0 },
+            handbrake: clamp(self.handbrake),
+        }
+    }
+}
+
+// velocities are in the car frame, `velocity_long` points forward and `velocity_lat` to the right
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VehicleState {
+    pub velocity_long: f64,
+    pub velocity_lat: f64,
+    pub yaw_rate: f64,
+    pub steer_angle: f64,
+    // time not yet simulated because it is shorter than a fixed step
+    pub pending: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Motion {
+    pub offset: FVec,
+    pub rotate: f64,
+}
+
+impl VehicleState {
+    pub fn speed(&self) -> f64 {
+        (self.velocity_long * self.velocity_long + self.velocity_lat * self.velocity_lat).sqrt()
+    }
+
+    pub fn world_velocity(&self, heading: f64) -> FVec {
+        let (sin, cos) = heading.sin_cos();
+        FVec::new(self.velocity_long * cos - self.velocity_lat * sin, self.velocity_long * sin + self.velocity_lat * cos)
+    }
+
+    // angle between where the car points and where it goes
+    pub fn slip_angle(&self) -> f64 {
+        if self.speed() < KINEMATIC_SPEED {
+            return 0.0;
+        }
+        self.velocity_lat.atan2(self.velocity_long.abs())
+    }
+
+    pub fn step(&mut self, params: &VehicleParams, input: &ControlInput, heading: f64, delta: f64) -> Motion {
+        let input = &input.clamped();
+        let mut motion = Motion::default();
+        self.pending += delta;
+        // a small tolerance keeps rounding from dropping a step when the tick is a multiple of it
+        while self.pending >= FIXED_STEP * (1.0 - 1e-9) {
+            self.pending = (self.pending - FIXED_STEP).max(0.0);
+            let dt = FIXED_STEP;
+            self.substep(params, input, dt);
+            let heading = heading + motion.rotate;
+            let (sin, cos) = heading.sin_cos();
+            motion.offset.x += (self.velocity_long * cos - self.velocity_lat * sin) * dt;
+            motion.offset.y += (self.velocity_long * sin + self.velocity_lat * cos) * dt;
+            motion.rotate += self.yaw_rate * dt;
+        }
+        motion
+    }
+
+    fn substep(&mut self, params: &VehicleParams, input: &ControlInput, dt: f64) {
+        let target_steer = input.steer * params.max_steer;
+        let max_steer_change = params.steer_speed * dt;
+        self.steer_angle += (target_steer - self.steer_angle).clamp(-max_steer_change, max_steer_change);
+
+        let drive_force = input.throttle * params.engine_force;
+        let force_long = self.longitudinal_force(params, input);
+        let normal_front = params.mass * GRAVITY * params.cg_to_rear / params.wheelbase();
+        let normal_rear = params.mass * GRAVITY * params.cg_to_front / params.wheelbase();
+
+        if self.velocity_long.abs() < KINEMATIC_SPEED {
+            self.velocity_long += force_long / params.mass * dt;
+            self.velocity_lat = 0.0;
+            self.yaw_rate = self.velocity_long * self.steer_angle.tan() / params.wheelbase();
+        } else {
+            let direction = self.velocity_long.signum();
+            let slip_front = (self.velocity_lat + params.cg_to_front * self.yaw_rate).atan2(self.velocity_long.abs()) - self.steer_angle * direction;
+            let slip_rear = (self.velocity_lat - params.cg_to_rear * self.yaw_rate).atan2(self.velocity_long.abs());
+            let max_front = params.grip * normal_front;
+            // the engine drives the rear wheels, what it uses is not available for cornering
+            // a locked rear axle keeps only part of its grip, that is what makes the handbrake turn the car
+            let grip_rear = params.grip * normal_rear * (1.0 - input.handbrake * (1.0 - params.handbrake_grip));
+            let max_rear = (grip_rear.powi(2) - drive_force.min(grip_rear).powi(2)).sqrt();
+            let lateral_front = (-params.cornering_stiffness * slip_front).clamp(-max_front, max_front);
+            let lateral_rear = (-params.cornering_stiffness * slip_rear).clamp(-max_rear, max_rear);
+
+            let accel_long = force_long / params.mass + self.yaw_rate * self.velocity_lat;
+            let accel_lat = (lateral_front * self.steer_angle.cos() + lateral_rear) / params.mass - self.yaw_rate * self.velocity_long;
+            let yaw_accel = (params.cg_to_front * lateral_front * self.steer_angle.cos() - params.cg_to_rear * lateral_rear) / params.inertia;
+            self.velocity_long += accel_long * dt;
+            self.velocity_lat += accel_lat * dt;
+            self.yaw_rate += yaw_accel * dt;
+        }
+
+        if input.throttle <= 0.0 && input.brake <= 0.0 && self.speed() < STOP_SPEED {
+            *self = VehicleState { steer_angle: self.steer_angle, pending: self.pending, ..VehicleState::default() };
+        }
+    }
+
+    fn longitudinal_force(&self, params: &VehicleParams, input: &ControlInput) -> f64 {
+        let (throttle, brake) = (input.throttle, input.brake);
+        let v = self.velocity_long;
+        let mut force = throttle * params.engine_force;
+        if input.handbrake > 0.0 && v.abs() > STOP_SPEED {
+            force -= v.signum() * input.handbrake * params.brake_force * HANDBRAKE_RATIO;
+        }
+        if brake > 0.0 {
+            if v > STOP_SPEED {
+                force -= brake * params.brake_force;
+            } else {
+                // once stopped the brake pedal drives backwards
+                force -= brake * params.engine_force * params.reverse_ratio;
+            }
+        }
+        force - params.drag * v * v.abs() - params.rolling_resistance * v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> VehicleParams {
+        VehicleParams::from_config(&CarConfig::default())
+    }
+
+    fn drive(state: &mut VehicleState, input: ControlInput, seconds: f64) -> f64 {
+        let mut heading = 0.0;
+        for _ in 0..(seconds * 60.0) as usize {
+            heading += state.step(&params(), &input, heading, 1.0 / 60.0).rotate;
+        }
+        heading
+    }
+
+    #[test]
+    fn test_reaches_top_speed() {
+        let mut state = VehicleState::default();
+        drive(&mut state, ControlInput { throttle: 1.0, ..ControlInput::default() }, 20.0);
+        let top_speed = CarConfig::default().top_speed;
+        assert!((state.velocity_long - top_speed).abs() < top_speed * 0.05, "should settle near top speed, got {}", state.velocity_long);
+    }
+
+    #[test]
+    fn test_coasts_to_stop() {
+        let mut state = VehicleState { velocity_long: 300.0, ..VehicleState::default() };
+        drive(&mut state, ControlInput::default(), 30.0);
+        assert_eq!(0.0, state.speed());
+    }
+
+    #[test]
+    fn test_brake_then_reverse() {
+        let mut state = VehicleState { velocity_long: 200.0, ..VehicleState::default() };
+        drive(&mut state, ControlInput { brake: 1.0, ..ControlInput::default() }, 3.0);
+        assert!(state.velocity_long < 0.0, "should be reversing, got {}", state.velocity_long);
+    }
+
+    #[test]
+    fn test_turns_right_at_low_speed() {
+        let mut state = VehicleState::default();
+        let heading = drive(&mut state, ControlInput { throttle: 0.1, steer: 1.0, ..ControlInput::default() }, 0.5);
+        assert!(heading > 0.0, "positive steer should turn clockwise");
+        assert_eq!(0.0, state.velocity_lat, "no slip at low speed");
+    }
+
+    #[test]
+    fn test_cornering_is_limited_by_grip() {
+        let mut state = VehicleState { velocity_long: 400.0, ..VehicleState::default() };
+        drive(&mut state, ControlInput { steer: 1.0, ..ControlInput::default() }, 1.0);
+        let lateral_accel = (state.velocity_long * state.yaw_rate).abs();
+        assert!(lateral_accel <= CarConfig::default().grip * GRAVITY * 1.05, "lateral acceleration {} exceeds grip", lateral_accel);
+    }
+
+    #[test]
+    fn test_power_oversteer_drifts() {
+        let mut state = VehicleState { velocity_long: 300.0, ..VehicleState::default() };
+        let mut max_slip: f64 = 0.0;
+        for _ in 0..4 {
+            drive(&mut state, ControlInput { throttle: 1.0, steer: 1.0, ..ControlInput::default() }, 0.25);
+            max_slip = max_slip.max(state.slip_angle().abs());
+        }
+        assert!(max_slip > 0.3, "full throttle at full lock should break the rear loose, slip angle {}", max_slip);
+    }
+
+    #[test]
+    fn test_handbrake_breaks_rear_loose() {
+        let mut state = VehicleState { velocity_long: 250.0, ..VehicleState::default() };
+        drive(&mut state, ControlInput { steer: 1.0, handbrake: 1.0, ..ControlInput::default() }, 0.5);
+        assert!(state.slip_angle().abs() > 0.2, "handbrake turn should slide, slip angle {}", state.slip_angle());
+    }
+
+    #[test]
+    fn test_input_is_clamped() {
+        let input = ControlInput { throttle: 2.0, brake: -1.0, steer: f64::NAN, handbrake: 0.5 }.clamped();
+        assert_eq!(ControlInput { throttle: 1.0, brake: 0.0, steer: 0.0, handbrake: 0.5 }, input);
+    }
+
+    #[test]
+    fn test_stable_under_full_lock() {
+        let mut state = VehicleState { velocity_long: 500.0, ..VehicleState::default() };
+        for steer in [1.0, -1.0, 1.0, -1.0] {
+            drive(&mut state, ControlInput { throttle: 1.0, steer, ..ControlInput::default() }, 2.0);
+            assert!(state.speed().is_finite() && state.speed() < 600.0, "speed blew up: {:?}", state);
+            assert!(state.yaw_rate.abs() < 20.0, "yaw rate blew up: {:?}", state);
+        }
+    }
+
+    #[test]
+    fn test_same_trajectory_at_any_tick_rate() {
+        let inputs = [
+            ControlInput { throttle: 1.0, ..ControlInput::default() },
+            ControlInput { throttle: 1.0, steer: 0.7, ..ControlInput::default() },
+            ControlInput { brake: 0.5, steer: -1.0, ..ControlInput::default() },
+            ControlInput { steer: 1.0, handbrake: 1.0, ..ControlInput::default() },
+        ];
+        let run = |hz: f64| {
+            let mut state = VehicleState::default();
+            let (mut heading, mut position) = (0.0, FVec::new(0.0, 0.0));
+            for input in inputs.iter() {
+                for _ in 0..(hz * 0.8).round() as usize {
+                    let motion = state.step(&params(), input, heading, 1.0 / hz);
+                    heading += motion.rotate;
+                    position.x += motion.offset.x;
+                    position.y += motion.offset.y;
+                }
+            }
+            (state, heading, position)
+        };
+        let (expected, heading, position) = run(60.0);
+        for hz in [30.0, 120.0] {
+            let (state, other_heading, other_position) = run(hz);
+            assert!((state.speed() - expected.speed()).abs() < 1e-6, "speed differs at {}Hz: {:?} vs {:?}", hz, state, expected);
+            assert!((other_heading - heading).abs() < 1e-6, "heading differs at {}Hz", hz);
+            assert!((other_position.x - position.x).abs() < 1e-6 && (other_position.y - position.y).abs() < 1e-6, "position differs at {}Hz", hz);
+        }
+    }
+
+    #[test]
+    fn test_slower_off_track() {
+        use super::super::surface::Surface;
+        let top_speed = |surface: Surface| {
+            let params = params().on_surface(&surface.properties());
+            let mut state = VehicleState::default();
+            for _ in 0..(20 * 60) {
+                state.step(&params, &ControlInput { throttle: 1.0, ..ControlInput::default() }, 0.0, 1.0 / 60.0);
+            }
+            state.velocity_long
+        };
+        assert!(top_speed(Surface::Grass) < top_speed(Surface::Asphalt) * 0.8, "grass should cost speed");
+        assert!(top_speed(Surface::Gravel) < top_speed(Surface::Grass), "gravel should be slower than grass");
+        let ice = params().on_surface(&Surface::Ice.properties());
+        assert!(ice.grip < params().grip * 0.5, "ice should have little grip");
+    }
+
+    #[test]
+    fn test_damage_costs_speed() {
+        let top_speed = |params: &VehicleParams| {
+            let mut state = VehicleState::default();
+            for _ in 0..(20 * 60) {
+                state.step(params, &ControlInput { throttle: 1.0, ..ControlInput::default() }, 0.0, 1.0 / 60.0);
+            }
+            state.velocity_long
+        };
+        let damaged = params().damaged(0.8);
+        assert!(top_speed(&damaged) < top_speed(&params()) * 0.9, "a damaged car should be slower");
+        assert!(damaged.max_steer < params().max_steer);
+        assert_eq!(params(), params().damaged(0.0));
+    }
+
+    #[test]
+    fn test_fuel_load_slows_acceleration() {
+        let speed_after = |params: &VehicleParams| {
+            let mut state = VehicleState::default();
+            for _ in 0..60 {
+                state.step(params, &ControlInput { throttle: 1.0, ..ControlInput::default() }, 0.0, 1.0 / 60.0);
+            }
+            state.velocity_long
+        };
+        assert!(speed_after(&params().fueled(0.3)) < speed_after(&params()) * 0.9, "a full tank should be heavier");
+    }
+}
@@ -1,9 +1,14 @@
-use browser::element;
+use browser::{element, query_string};
 use engine::RenderLoop;
-use game::RacingGame;
+use game::{RacingGame, show_error, load_tracks, generate_tracks, TRACKS_URL};
+use error::GameError;
+use config::{GameConfig, CONFIG_URL};
 use wasm_bindgen::prelude::*;
-use web_sys::{ HtmlCanvasElement, console };
+use web_sys::HtmlCanvasElement;
 use wasm_bindgen::{JsCast, JsValue, closure, prelude::Closure};
+mod logging;
+mod error;
+mod config;
 mod browser;
 mod engine;
 mod game;
@@ -48,11 +53,23 @@ pub async fn main_js() -> Result<(), JsValue> {
     #[cfg(debug_assertions)]
     console_error_panic_hook::set_once();
     // Your code goes here!
-    console::log_1(&JsValue::from_str("start game!"));
+    log_info!("start game!");
 
-    let game_loop = RenderLoop::new();
-    let canvas = element::<HtmlCanvasElement>("game_canvas").ok_or(JsValue::from_str("did not find canvas"))?;
-    let racing_game = RacingGame::new(canvas.width() as f64, canvas.height() as f64).await;
-    game_loop.start(racing_game, canvas).await?;
+    let canvas = element::<HtmlCanvasElement>("game_canvas").ok_or(GameError::ElementNotFound("game_canvas".to_string()))?;
+    if let Err(err) = start(canvas.clone()).await {
+        log_error!("failed to start game: {}", err);
+        show_error(&canvas, &err);
+        return Err(err.into());
+    }
     Ok(())
 }
+
+async fn start(canvas: HtmlCanvasElement) -> Result<(), GameError> {
+    let game_loop = RenderLoop::new();
+    let mut config = GameConfig::load(CONFIG_URL).await?;
+    config.apply_query(&query_string())?;
+    let mut tracks = load_tracks(TRACKS_URL).await;
+    tracks.extend(generate_tracks(&config));
+    let racing_game = RacingGame::new(canvas.width() as f64, canvas.height() as f64, config, tracks).await?;
+    game_loop.start(racing_game, canvas).await
+}
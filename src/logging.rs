@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(value: &str) -> Option<Level> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// filter syntax is `default_level,module=level,...`, e.g. `info,game::car=trace`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    default: Level,
+    modules: Vec<(String, Level)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter { default: Level::Info, modules: vec![] }
+    }
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = Level::parse(level).ok_or(format!("unknown log level in `{}`", directive))?;
+                    filter.modules.push((module.trim().to_string(), level));
+                },
+                None => {
+                    filter.default = Level::parse(directive).ok_or(format!("unknown log level `{}`", directive))?;
+                },
+            }
+        }
+        // longest module prefix wins
+        filter.modules.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+        Ok(filter)
+    }
+
+    pub fn enabled(&self, level: Level, module_path: &str) -> bool {
+        let module = strip_crate(module_path);
+        let max_level = self.modules.iter()
+            .find(|(prefix, _)| module == prefix || module.starts_with(&format!("{}::", prefix)))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default);
+        level != Level::Off && level <= max_level
+    }
+}
+
+fn strip_crate(module_path: &str) -> &str {
+    module_path.split_once("::").map(|(_, module)| module).unwrap_or("")
+}
+
+thread_local! {
+    static FILTER: RefCell<Filter> = RefCell::new(Filter::default());
+}
+
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    FILTER.with(|filter| filter.borrow().enabled(level, module_path))
+}
+
+pub fn set_filter(spec: &str) -> Result<(), String> {
+    let filter = Filter::parse(spec)?;
+    FILTER.with(|current| *current.borrow_mut() = filter);
+    Ok(())
+}
+
+// exported so the filter can be changed from the browser console
+#[wasm_bindgen(js_name = setLogFilter)]
+pub fn set_log_filter(spec: &str) -> Result<(), JsValue> {
+    set_filter(spec).map_err(|err| JsValue::from_str(&err))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(level: Level, module_path: &str, message: &str) {
+    use web_sys::console;
+    let line = JsValue::from_str(&format!("[{} {}] {}", level, strip_crate(module_path), message));
+    match level {
+        Level::Error => console::error_1(&line),
+        Level::Warn => console::warn_1(&line),
+        Level::Info => console::info_1(&line),
+        Level::Debug | Level::Trace => console::debug_1(&line),
+        Level::Off => {},
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(level: Level, module_path: &str, message: &str) {
+    eprintln!("[{} {}] {}", level, strip_crate(module_path), message);
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($t:tt)*) => {
+        if $crate::logging::enabled($level, module_path!()) {
+            $crate::logging::write($level, module_path!(), &format!($($t)*));
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($t:tt)*) => ($crate::log_at!($crate::logging::Level::Error, $($t)*))
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($t:tt)*) => ($crate::log_at!($crate::logging::Level::Warn, $($t)*))
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($t:tt)*) => ($crate::log_at!($crate::logging::Level::Info, $($t)*))
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($t:tt)*) => ($crate::log_at!($crate::logging::Level::Debug, $($t)*))
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($t:tt)*) => ($crate::log_at!($crate::logging::Level::Trace, $($t)*))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_filter() {
+        let filter = Filter::default();
+        assert!(filter.enabled(Level::Info, "racing::game::car"));
+        assert!(!filter.enabled(Level::Debug, "racing::game::car"));
+    }
+
+    #[test]
+    fn test_module_filter() {
+        let filter = Filter::parse("warn,game=info,game::car=trace").unwrap();
+        assert!(filter.enabled(Level::Trace, "racing::game::car"), "most specific module should win");
+        assert!(filter.enabled(Level::Info, "racing::game::stage"));
+        assert!(!filter.enabled(Level::Debug, "racing::game::stage"));
+        assert!(!filter.enabled(Level::Info, "racing::engine"));
+        assert!(!filter.enabled(Level::Trace, "racing::game::car_sensor"), "prefix should match whole modules");
+    }
+
+    #[test]
+    fn test_off() {
+        let filter = Filter::parse("off").unwrap();
+        assert!(!filter.enabled(Level::Error, "racing::game"));
+    }
+
+    #[test]
+    fn test_invalid_level() {
+        assert!(Filter::parse("game=loud").is_err());
+    }
+}
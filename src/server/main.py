@@ -1,4 +1,5 @@
 import os
+import random
 import neat
 import flask
 from flask import Flask, request, jsonify, make_response
@@ -8,10 +9,25 @@ app = Flask(__name__)
 CORS(app)
 local_dir = os.path.dirname(__file__)
 config_file = os.path.join(local_dir, 'config-feedforward')
-config = neat.Config(neat.DefaultGenome, neat.DefaultReproduction,
-                     neat.DefaultSpeciesSet, neat.DefaultStagnation,
-                     config_file)
-p = neat.Population(config)
+# one population per car class, keyed by the `population` field of each request,
+# each keeps its own config in `p.config` so populations of different sizes can coexist
+populations = {}
+
+def population_config(pop_size, num_inputs, num_outputs):
+    config = neat.Config(neat.DefaultGenome, neat.DefaultReproduction,
+                         neat.DefaultSpeciesSet, neat.DefaultStagnation,
+                         config_file)
+    config.pop_size = pop_size
+    genome_config = config.genome_config
+    genome_config.num_inputs = num_inputs
+    genome_config.num_outputs = num_outputs
+    # the node keys are derived from the sizes when the config is read
+    genome_config.input_keys = [-i - 1 for i in range(num_inputs)]
+    genome_config.output_keys = list(range(num_outputs))
+    return config
+
+def population_for(json_request):
+    return populations.get(json_request.get("population", "default"))
 
 @app.route('/api/gen_network', methods=['POST'])
 def gen_network():
@@ -19,19 +35,31 @@ def gen_network():
     num_of_cars = json_request["num_of_cars"]
     num_of_args = json_request["num_of_args"]
     num_of_outputs = json_request["num_of_outputs"]
+    name = json_request.get("population", "default")
+    existing = populations.get(name)
+    # a race keeps what the class has learned so far, a population is only made when none fits
+    if json_request.get("keep", False) and existing is not None \
+            and existing.config.genome_config.num_inputs == num_of_args \
+            and existing.config.genome_config.num_outputs == num_of_outputs \
+            and len(existing.population) >= num_of_cars:
+        return jsonify({})
 
-    config.pop_size = num_of_cars
-    config.num_of_input = num_of_args
-    config.num_outputs = num_of_outputs
-
-    p = neat.Population(config)
-    print(p.population.values())
+    seed = json_request.get("seed")
+    if seed is not None:
+        # seeded per class so two classes do not start out as the same population
+        random.seed("{}-{}".format(seed, name))
+    p = neat.Population(population_config(num_of_cars, num_of_args, num_of_outputs))
+    populations[name] = p
+    app.logger.debug("new population %s of %d genomes", name, len(p.population))
     return jsonify({})
 
 @app.route('/api/evaluate_network', methods=['POST'])
 def evalute():
     data = request.json
     inputs = data["inputs"]
+    p = population_for(data)
+    if p is None:
+        return make_response('unknown population', 404)
     genomes = p.population.values()
     outputs = []
     if len(inputs) > len(genomes):
@@ -50,6 +78,9 @@ def evalute():
 def set_fitnesses():
     json = request.json
     fitnesses = json['fitnesses']
+    p = population_for(json)
+    if p is None:
+        return make_response('unknown population', 404)
     genomes = p.population.values()
     if len(fitnesses) < len(genomes):
         response = make_response('', 422)  # 204 indicates No Content
@@ -63,10 +94,12 @@ def set_fitnesses():
 
 @app.route('/api/next_gen', methods=['POST'])
 def next_gen():
-    for genome in p.population.values():
-        print(genome.fitness)
-    for sid, s in p.species.species.items():
-        print(s.get_fitnesses())
+    p = population_for(request.get_json(silent=True) or {})
+    if p is None:
+        return make_response('unknown population', 404)
+    fitnesses = [genome.fitness for genome in p.population.values() if genome.fitness is not None]
+    app.logger.debug("generation %d best fitness %s over %d species", p.generation,
+                     max(fitnesses, default=None), len(p.species.species))
 
     p.population = p.reproduction.reproduce(p.config, p.species, p.config.pop_size, p.generation)
     p.species.speciate(p.config, p.population, p.generation)